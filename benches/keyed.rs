@@ -0,0 +1,88 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use throttle::{KeyedThrottle, ShardedThrottle};
+
+const TIMEOUT: Duration = Duration::from_millis(1);
+const THRESHOLD: usize = 100;
+const KEYS: u64 = 1024;
+const SHARDS: usize = 64;
+
+/// Runs `iters` calls of `f` split across `threads` threads and returns the wall time.
+///
+/// Each call receives a key that differs between threads and calls.
+fn contend<F: Fn(u64) + Send + Sync + 'static>(threads: usize, iters: u64, f: F) -> Duration {
+    let f = Arc::new(f);
+    let barrier = Arc::new(Barrier::new(threads + 1));
+    let handles: Vec<_> = (0..threads as u64)
+        .map(|thread| {
+            let f = Arc::clone(&f);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                for i in 0..iters / threads as u64 {
+                    f((thread * 7919 + i) % KEYS);
+                }
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn accept(c: &mut Criterion) {
+    let mut group = c.benchmark_group("keyed_accept");
+    group.throughput(Throughput::Elements(1));
+    for &threads in &[1, 2, 4, 8, 16] {
+        group.bench_with_input(
+            BenchmarkId::new("ShardedThrottle", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    let throttle = ShardedThrottle::new(TIMEOUT, THRESHOLD, SHARDS);
+                    contend(threads, iters, move |key| {
+                        let _ = throttle.accept(&key);
+                    })
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("Mutex<KeyedThrottle>", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    let throttle = Mutex::new(KeyedThrottle::new(TIMEOUT, THRESHOLD));
+                    contend(threads, iters, move |key| {
+                        let _ = throttle.lock().unwrap().accept(&key);
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, accept);
+criterion_main!(benches);
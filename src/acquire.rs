@@ -0,0 +1,190 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Asynchronous acquisition, enabled by the `tokio` or `async-std` feature.
+//!
+//! If both features are enabled, the tokio timer is used.
+
+use std::future;
+use std::task::Poll;
+use std::time::Instant;
+
+use crate::fair::{Turn, Wake};
+use crate::{AcceptNError, FairThrottle, SyncThrottle, Throttle};
+
+#[cfg(feature = "tokio")]
+async fn sleep_until(at: Instant) {
+    tokio::time::sleep_until(at.into()).await
+}
+
+#[cfg(not(feature = "tokio"))]
+async fn sleep_until(at: Instant) {
+    async_std::task::sleep(at.saturating_duration_since(Instant::now())).await
+}
+
+impl Throttle {
+    /// Waits until an operation is accepted.
+    ///
+    /// This sleeps until the Instant returned by [`accept`](Throttle::accept) and retries. The
+    /// returned future is cancellation-safe: nothing is recorded until it completes, so dropping
+    /// it does not consume a slot.
+    ///
+    /// Requires the `tokio` or `async-std` feature.
+    ///
+    /// ```
+    /// # #[cfg(feature = "tokio")]
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() { example().await }
+    /// # #[cfg(not(feature = "tokio"))]
+    /// # fn main() { async_std::task::block_on(example()) }
+    /// # async fn example() {
+    /// use std::time::{Duration, Instant};
+    /// use throttle::Throttle;
+    ///
+    /// let mut throttle = Throttle::new(Duration::from_millis(100), 1);
+    /// let start = Instant::now();
+    /// throttle.acquire().await;
+    /// throttle.acquire().await;
+    /// assert!(start.elapsed() >= Duration::from_millis(100));
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub async fn acquire(&mut self) {
+        while let Err(at) = self.accept() {
+            sleep_until(at).await;
+        }
+    }
+
+    /// Waits until an operation that costs `cost` units of the threshold is accepted.
+    ///
+    /// See [`acquire`](Throttle::acquire). Returns [`AcceptNError::TooLarge`] immediately if
+    /// `cost` can never be accepted.
+    ///
+    /// Requires the `tokio` or `async-std` feature.
+    pub async fn acquire_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        loop {
+            match self.accept_n(cost) {
+                Err(AcceptNError::Full(at)) => sleep_until(at).await,
+                result => return result,
+            }
+        }
+    }
+}
+
+impl SyncThrottle {
+    /// Waits until an operation is accepted.
+    ///
+    /// See [`Throttle::acquire`]. The lock is not held while waiting.
+    ///
+    /// Requires the `tokio` or `async-std` feature.
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub async fn acquire(&self) {
+        while let Err(at) = self.accept() {
+            sleep_until(at).await;
+        }
+    }
+
+    /// Waits until an operation that costs `cost` units of the threshold is accepted.
+    ///
+    /// See [`Throttle::acquire_n`].
+    ///
+    /// Requires the `tokio` or `async-std` feature.
+    pub async fn acquire_n(&self, cost: usize) -> Result<(), AcceptNError> {
+        loop {
+            match self.accept_n(cost) {
+                Err(AcceptNError::Full(at)) => sleep_until(at).await,
+                result => return result,
+            }
+        }
+    }
+}
+
+impl FairThrottle {
+    /// Waits until an operation is accepted, after all operations that started waiting earlier.
+    ///
+    /// The returned future is cancellation-safe: dropping it leaves the queue without consuming a
+    /// slot, and wakes the next waiter if it was at the head of the queue.
+    ///
+    /// Requires the `tokio` or `async-std` feature.
+    ///
+    /// ```
+    /// # #[cfg(not(feature = "tokio"))]
+    /// # fn main() {}
+    /// # #[cfg(feature = "tokio")]
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// use std::time::Duration;
+    /// use throttle::FairThrottle;
+    ///
+    /// let throttle = FairThrottle::new(Duration::from_millis(100), 1);
+    /// throttle.acquire().await;
+    ///
+    /// let cancelled = tokio::time::timeout(Duration::from_millis(10), throttle.acquire()).await;
+    /// assert!(cancelled.is_err());
+    /// assert_eq!(throttle.waiting(), 0, "The dropped future has left the queue");
+    /// assert_eq!(throttle.size(), 1, "The dropped future has not consumed a slot");
+    ///
+    /// throttle.acquire().await;
+    /// assert_eq!(throttle.size(), 1);
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub async fn acquire(&self) {
+        let ticket = match self.enqueue(Wake::Task(None)) {
+            Some(ticket) => ticket,
+            None => return,
+        };
+
+        let mut guard = CancelGuard {
+            throttle: self,
+            ticket: Some(ticket),
+        };
+        loop {
+            let turn = future::poll_fn(|cx| match self.turn(ticket, Some(cx.waker())) {
+                Turn::Queued => Poll::Pending,
+                turn => Poll::Ready(turn),
+            })
+            .await;
+            match turn {
+                Turn::Accepted => {
+                    guard.ticket = None;
+                    return;
+                }
+                Turn::RetryAt(at) => sleep_until(at).await,
+                Turn::Queued => unreachable!("queued turns are pending"),
+            }
+        }
+    }
+}
+
+/// Leaves the queue of a FairThrottle if the acquiring future is dropped.
+struct CancelGuard<'a> {
+    throttle: &'a FairThrottle,
+    ticket: Option<u64>,
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket {
+            self.throttle.cancel(ticket);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{AcceptNError, Clock, SystemClock};
+
+/// AtomicGcra is a lock-free [`Gcra`](crate::Gcra) that can be shared between threads.
+///
+/// The theoretical arrival time is stored in an `AtomicU64` as nanoseconds since the creation of
+/// the limiter, and updated with compare-and-swap. All methods take `&self`.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use throttle::{AtomicGcra, MockClock};
+///
+/// let clock = MockClock::new();
+/// let gcra = Arc::new(AtomicGcra::with_clock(Duration::from_secs(1), 100, clock.clone()));
+///
+/// let threads: Vec<_> = (0..8)
+///     .map(|_| {
+///         let gcra = Arc::clone(&gcra);
+///         std::thread::spawn(move || (0..1000).filter(|_| gcra.accept().is_ok()).count())
+///     })
+///     .collect();
+/// let accepted: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+/// assert_eq!(accepted, 100, "the limiter never admits more than the burst");
+///
+/// clock.advance(Duration::from_millis(50));
+/// let threads: Vec<_> = (0..8)
+///     .map(|_| {
+///         let gcra = Arc::clone(&gcra);
+///         std::thread::spawn(move || (0..1000).filter(|_| gcra.accept_n(2).is_ok()).count())
+///     })
+///     .collect();
+/// let accepted: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+/// assert_eq!(accepted, 2, "5 operations were emitted in 50ms");
+/// ```
+pub struct AtomicGcra<C = SystemClock> {
+    timeout: u64,
+    threshold: usize,
+    /// The time to emit one operation, in nanoseconds
+    interval: u64,
+    base: Instant,
+    /// The theoretical arrival time, in nanoseconds since `base`
+    tat: AtomicU64,
+    clock: C,
+}
+
+impl AtomicGcra {
+    /// Creates a new AtomicGcra
+    ///
+    /// # Panics
+    /// Panics if `threshold` is zero or exceeds the number of nanoseconds in `timeout`, or if
+    /// `timeout` does not fit in 64-bit nanoseconds.
+    pub fn new(timeout: Duration, threshold: usize) -> AtomicGcra {
+        AtomicGcra::with_clock(timeout, threshold, SystemClock)
+    }
+}
+
+impl<C: Clock> AtomicGcra<C> {
+    /// Creates a new AtomicGcra that reads the time from `clock`
+    ///
+    /// # Panics
+    /// Panics if `threshold` is zero or exceeds the number of nanoseconds in `timeout`, or if
+    /// `timeout` does not fit in 64-bit nanoseconds.
+    pub fn with_clock(timeout: Duration, threshold: usize, clock: C) -> AtomicGcra<C> {
+        assert!(threshold > 0, "threshold must be positive");
+        let timeout = u64::try_from(timeout.as_nanos()).expect("timeout is too long");
+        assert!(
+            timeout >= threshold as u64,
+            "timeout must be at least threshold nanoseconds"
+        );
+        AtomicGcra {
+            timeout,
+            threshold,
+            interval: timeout / threshold as u64,
+            base: clock.now(),
+            tat: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.clock
+            .now()
+            .saturating_duration_since(self.base)
+            .as_nanos() as u64
+    }
+
+    /// Returns the number of operations that have not been emitted yet
+    pub fn size(&self) -> usize {
+        let backlog = self.tat.load(Ordering::Acquire).saturating_sub(self.now());
+        backlog.div_ceil(self.interval) as usize
+    }
+
+    /// Checks that the limiter is available to accept.
+    ///
+    /// The availability may change at any time since other threads may accept concurrently.
+    pub fn available(&self) -> bool {
+        let now = self.now();
+        let tat = self.tat.load(Ordering::Acquire);
+        tat.max(now) + self.interval - now <= self.timeout
+    }
+
+    /// Attempts to accept an operation.
+    ///
+    /// See [`Gcra::accept`](crate::Gcra::accept).
+    pub fn accept(&self) -> Result<(), Instant> {
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => unreachable!("threshold is positive"),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n) for the meaning of the errors.
+    pub fn accept_n(&self, cost: usize) -> Result<(), AcceptNError> {
+        if cost > self.threshold {
+            return Err(AcceptNError::TooLarge);
+        }
+
+        let now = self.now();
+        let increment = self.interval * cost as u64;
+        accept_at(&self.tat, now, increment, self.timeout)
+            .map_err(|allow_at| AcceptNError::Full(self.base + Duration::from_nanos(allow_at)))
+    }
+}
+
+/// Performs a GCRA accept on `tat` with all times in nanoseconds from the same origin.
+///
+/// On failure, Err is returned with the time at which the operation can be accepted.
+pub(crate) fn accept_at(
+    tat: &AtomicU64,
+    now: u64,
+    increment: u64,
+    timeout: u64,
+) -> Result<(), u64> {
+    let mut current = tat.load(Ordering::Acquire);
+    loop {
+        let new_tat = current.max(now) + increment;
+        let allow_at = new_tat.saturating_sub(timeout);
+        if allow_at > now {
+            return Err(allow_at);
+        }
+
+        match tat.compare_exchange_weak(current, new_tat, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return Ok(()),
+            Err(actual) => current = actual,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs a Redis-compatible throttle server. See the `throttle::resp` module for the commands.
+//!
+//! ```text
+//! throttle-resp [--bind <addr>]
+//! ```
+
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use std::sync::Arc;
+
+use throttle::resp::RespServer;
+
+const USAGE: &str = "usage: throttle-resp [--bind <addr>]";
+
+fn fail(message: &str) -> ! {
+    eprintln!("throttle-resp: {}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn main() {
+    let mut bind = String::from("127.0.0.1:6379");
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => {
+                bind = args
+                    .next()
+                    .unwrap_or_else(|| fail(&format!("missing value for {}", arg)))
+            }
+            _ => fail(&format!("unknown argument {}", arg)),
+        }
+    }
+
+    let mut server = RespServer::new();
+    server
+        .on_accept_error(|err| eprintln!("throttle-resp: failed to accept a connection: {}", err));
+    let server = Arc::new(server);
+    if let Err(err) = TcpListener::bind(bind).and_then(|listener| server.serve(listener)) {
+        eprintln!("throttle-resp: {}", err);
+        process::exit(1);
+    }
+}
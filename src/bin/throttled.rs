@@ -0,0 +1,95 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs a rate-limit server. See the `throttle::server` module for the protocol.
+//!
+//! ```text
+//! throttled (--tcp <addr> | --unix <path>) --limit <name>:<threshold>:<timeout ms>...
+//! ```
+
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use throttle::server::Server;
+
+const USAGE: &str =
+    "usage: throttled (--tcp <addr> | --unix <path>) --limit <name>:<threshold>:<timeout ms>...";
+
+enum Listen {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(String),
+}
+
+fn parse_limit(spec: &str) -> Option<(&str, usize, Duration)> {
+    let mut parts = spec.split(':');
+    let name = parts.next()?;
+    let threshold = parts.next()?.parse().ok()?;
+    let timeout = Duration::from_millis(parts.next()?.parse().ok()?);
+    if name.is_empty() || parts.next().is_some() {
+        return None;
+    }
+    Some((name, threshold, timeout))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("throttled: {}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn main() {
+    let mut server = Server::new();
+    let mut listen = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "--tcp" => listen = Some(Listen::Tcp(value())),
+            #[cfg(unix)]
+            "--unix" => listen = Some(Listen::Unix(value())),
+            "--limit" => {
+                let spec = value();
+                match parse_limit(&spec) {
+                    Some((name, threshold, timeout)) => server.add_limit(name, timeout, threshold),
+                    None => fail(&format!("invalid limit {}", spec)),
+                }
+            }
+            _ => fail(&format!("unknown argument {}", arg)),
+        }
+    }
+
+    server.on_accept_error(|err| eprintln!("throttled: failed to accept a connection: {}", err));
+    let server = Arc::new(server);
+    let result = match listen {
+        Some(Listen::Tcp(addr)) => {
+            TcpListener::bind(addr).and_then(|listener| server.serve_tcp(listener))
+        }
+        #[cfg(unix)]
+        Some(Listen::Unix(path)) => std::os::unix::net::UnixListener::bind(path)
+            .and_then(|listener| server.serve_unix(listener)),
+        None => fail("no address to listen on"),
+    };
+    if let Err(err) = result {
+        eprintln!("throttled: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,241 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use crate::{AcceptNError, Clock, SystemClock, Throttle};
+
+/// The key to evict when a [`BoundedKeyedThrottle`] is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// Evicts the key that was accepted or rejected least recently.
+    LeastRecentlyUsed,
+    /// Evicts the key whose newest accepted operation is the oldest.
+    ///
+    /// If any key has an empty throttle, this evicts such a key.
+    OldestNewest,
+}
+
+/// BoundedKeyedThrottle is a [`KeyedThrottle`](crate::KeyedThrottle) with a hard limit on the
+/// number of tracked keys.
+///
+/// When a new key is accepted while `capacity` keys are tracked, a key is evicted according to
+/// the [`Eviction`] policy. An evicted key starts with an empty throttle if it is seen again, so
+/// the number of evictions of non-empty throttles is exposed to detect pressure on the limit.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{BoundedKeyedThrottle, Eviction, MockClock};
+///
+/// let clock = MockClock::new();
+/// let mut throttle = BoundedKeyedThrottle::with_clock(
+///     Duration::from_secs(10),
+///     1,
+///     2,
+///     Eviction::LeastRecentlyUsed,
+///     clock.clone(),
+/// );
+///
+/// throttle.accept("alice").expect("alice has not been throttled");
+/// throttle.accept("bob").expect("bob has not been throttled");
+/// throttle.accept("alice").expect_err("alice should be throttled");
+///
+/// throttle.accept("carol").expect("carol has not been throttled");
+/// assert_eq!(throttle.len(), 2);
+/// assert_eq!(throttle.evictions(), 1, "bob was used least recently");
+/// throttle.accept("alice").expect_err("alice should still be throttled");
+/// ```
+pub struct BoundedKeyedThrottle<K, C = SystemClock> {
+    timeout: Duration,
+    threshold: usize,
+    capacity: usize,
+    policy: Eviction,
+    entries: HashMap<K, Entry<C>>,
+    /// The keys in eviction order
+    order: BTreeMap<Stamp, K>,
+    tick: u64,
+    evictions: u64,
+    clock: C,
+}
+
+/// The position of a key in the eviction order; the tick breaks ties between equal Instants.
+type Stamp = (Instant, u64);
+
+struct Entry<C> {
+    throttle: Throttle<C>,
+    stamp: Stamp,
+}
+
+impl<K: Hash + Eq + Clone> BoundedKeyedThrottle<K> {
+    /// Creates a new BoundedKeyedThrottle that tracks at most `capacity` keys
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn new(
+        timeout: Duration,
+        threshold: usize,
+        capacity: usize,
+        policy: Eviction,
+    ) -> BoundedKeyedThrottle<K> {
+        BoundedKeyedThrottle::with_clock(timeout, threshold, capacity, policy, SystemClock)
+    }
+}
+
+impl<K: Hash + Eq + Clone, C: Clock + Clone> BoundedKeyedThrottle<K, C> {
+    /// Creates a new BoundedKeyedThrottle that tracks at most `capacity` keys and reads the time
+    /// from `clock`
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn with_clock(
+        timeout: Duration,
+        threshold: usize,
+        capacity: usize,
+        policy: Eviction,
+        clock: C,
+    ) -> BoundedKeyedThrottle<K, C> {
+        assert!(capacity > 0, "capacity must be positive");
+        BoundedKeyedThrottle {
+            timeout,
+            threshold,
+            capacity,
+            policy,
+            entries: HashMap::with_capacity(capacity),
+            order: BTreeMap::new(),
+            tick: 0,
+            evictions: 0,
+            clock,
+        }
+    }
+
+    /// Returns the number of tracked keys
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no keys are tracked
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the maximum number of tracked keys
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of keys that were evicted while their throttle was not empty
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Removes the throttles of all keys that have become empty
+    pub fn flush(&mut self) {
+        let order = &mut self.order;
+        self.entries.retain(|_, entry| {
+            let keep = entry.throttle.size() > 0;
+            if !keep {
+                order.remove(&entry.stamp);
+            }
+            keep
+        });
+    }
+
+    fn next_stamp(&mut self) -> Stamp {
+        self.tick += 1;
+        (self.clock.now(), self.tick)
+    }
+
+    /// Returns the number of remaining items in the throttle of `key`
+    pub fn size<Q>(&mut self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries
+            .get_mut(key)
+            .map_or(0, |entry| entry.throttle.size())
+    }
+
+    /// Checks that the throttle of `key` is available to accept.
+    ///
+    /// See [`Throttle::available`].
+    pub fn available<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.size(key) < self.threshold
+    }
+
+    /// Attempts to accept an operation for `key`.
+    ///
+    /// See [`Throttle::accept`].
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn accept<Q>(&mut self, key: &Q) -> Result<(), Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        match self.accept_n(key, 1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("BoundedKeyedThrottle threshold is zero"),
+        }
+    }
+
+    /// Attempts to accept an operation for `key` that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`].
+    pub fn accept_n<Q>(&mut self, key: &Q, cost: usize) -> Result<(), AcceptNError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let stamp = self.next_stamp();
+        if let Some(entry) = self.entries.get_mut(key) {
+            let result = entry.throttle.accept_n(cost);
+            if self.policy == Eviction::LeastRecentlyUsed || result.is_ok() {
+                let owned = self.order.remove(&entry.stamp).expect("entry is ordered");
+                self.order.insert(stamp, owned);
+                entry.stamp = stamp;
+            }
+            return result;
+        }
+
+        let mut throttle = Throttle::with_clock(self.timeout, self.threshold, self.clock.clone());
+        throttle.accept_n(cost)?;
+        if self.entries.len() >= self.capacity {
+            self.evict();
+        }
+        let key = key.to_owned();
+        self.order.insert(stamp, key.clone());
+        self.entries.insert(key, Entry { throttle, stamp });
+        Ok(())
+    }
+
+    fn evict(&mut self) {
+        if let Some((_, key)) = self.order.pop_first() {
+            let mut entry = self.entries.remove(&key).expect("ordered key is tracked");
+            if entry.throttle.size() > 0 {
+                self.evictions += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::{nanos_to_duration, AcceptNError, Clock, SystemClock};
+
+/// BucketedThrottle is a sliding log that groups operations into time buckets.
+///
+/// The `timeout` is divided into `granularity` buckets, and each bucket only stores the number of
+/// operations accepted in it. Memory usage is therefore bounded by `granularity` instead of
+/// `threshold`.
+///
+/// A bucket expires `timeout` after its end, so operations are never expired earlier than in
+/// [`Throttle`](crate::Throttle), but may be expired up to `timeout / granularity` later.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{BucketedThrottle, Clock, MockClock};
+///
+/// let clock = MockClock::new();
+/// // a one-minute window with one-second buckets
+/// let mut throttle = BucketedThrottle::with_clock(Duration::from_secs(60), 3, 60, clock.clone());
+///
+/// throttle.accept().expect("The throttle is empty");
+/// clock.advance(Duration::from_millis(300));
+/// throttle.accept().expect("The throttle has two more spaces");
+/// clock.advance(Duration::from_secs(1));
+/// throttle.accept().expect("The throttle has one more space");
+/// assert_eq!(throttle.size(), 3);
+///
+/// // the first bucket ended 0.3s ago and expires a minute after its end
+/// assert_eq!(throttle.accept(), Err(clock.now() + Duration::from_millis(59_700)));
+/// clock.advance(Duration::from_millis(59_700));
+/// assert_eq!(throttle.size(), 1);
+/// ```
+pub struct BucketedThrottle<C = SystemClock> {
+    timeout: Duration,
+    threshold: usize,
+    /// The width of each bucket, in nanoseconds
+    width: u128,
+    origin: Instant,
+    /// Bucket indices since `origin` and their counts, only for non-empty buckets
+    buckets: VecDeque<(u128, usize)>,
+    total: usize,
+    clock: C,
+}
+
+impl BucketedThrottle {
+    /// Creates a new BucketedThrottle with `granularity` buckets
+    ///
+    /// # Panics
+    /// Panics if `granularity` is zero or greater than the number of nanoseconds in `timeout`.
+    pub fn new(timeout: Duration, threshold: usize, granularity: usize) -> BucketedThrottle {
+        BucketedThrottle::with_clock(timeout, threshold, granularity, SystemClock)
+    }
+}
+
+impl<C: Clock> BucketedThrottle<C> {
+    /// Creates a new BucketedThrottle with `granularity` buckets that reads the time from `clock`
+    ///
+    /// # Panics
+    /// Panics if `granularity` is zero or greater than the number of nanoseconds in `timeout`.
+    pub fn with_clock(
+        timeout: Duration,
+        threshold: usize,
+        granularity: usize,
+        clock: C,
+    ) -> BucketedThrottle<C> {
+        assert!(granularity > 0, "granularity must be positive");
+        let width = timeout.as_nanos() / granularity as u128;
+        assert!(width > 0, "granularity is finer than a nanosecond");
+        BucketedThrottle {
+            timeout,
+            threshold,
+            width,
+            origin: clock.now(),
+            buckets: VecDeque::with_capacity(granularity + 1),
+            total: 0,
+            clock,
+        }
+    }
+
+    /// Returns the time at which the bucket `index` expires.
+    fn expiry(&self, index: u128) -> Instant {
+        self.origin + nanos_to_duration((index + 1) * self.width) + self.timeout
+    }
+
+    /// Expires old buckets and returns the index of the current bucket.
+    fn flush(&mut self) -> u128 {
+        let now = self.clock.now();
+        while let Some(&(index, count)) = self.buckets.front() {
+            if self.expiry(index) <= now {
+                self.buckets.pop_front();
+                self.total -= count;
+            } else {
+                break;
+            }
+        }
+        now.saturating_duration_since(self.origin).as_nanos() / self.width
+    }
+
+    /// Returns the earliest time at which `cost` more units fit into the throttle.
+    ///
+    /// `cost` must not exceed the threshold.
+    fn retry_at(&self, cost: usize) -> Instant {
+        let mut remaining = self.total;
+        for &(index, count) in &self.buckets {
+            remaining -= count;
+            if remaining + cost <= self.threshold {
+                return self.expiry(index);
+            }
+        }
+        unreachable!("cost exceeds threshold")
+    }
+
+    /// Returns the number of remaining items in the throttle
+    pub fn size(&mut self) -> usize {
+        self.flush();
+        self.total
+    }
+
+    /// Checks that the throttle is available to accept.
+    ///
+    /// The same race conditions as [`Throttle::available`](crate::Throttle::available) apply.
+    pub fn available(&mut self) -> bool {
+        self.size() < self.threshold
+    }
+
+    /// Attempts to accept an operation and increment the throttle.
+    ///
+    /// On failure, Err is returned with an Instant indicating the time that the throttle is
+    /// available again.
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn accept(&mut self) -> Result<(), Instant> {
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("BucketedThrottle threshold is zero"),
+        }
+    }
+
+    /// Checks whether an operation that costs `cost` units of the threshold would be accepted
+    /// now, without recording it.
+    ///
+    /// The errors are the same as [`accept_n`](BucketedThrottle::accept_n).
+    pub fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        self.check(cost).map(|_| ())
+    }
+
+    /// Checks `cost` and returns the index of the current bucket.
+    fn check(&mut self, cost: usize) -> Result<u128, AcceptNError> {
+        if cost > self.threshold {
+            return Err(AcceptNError::TooLarge);
+        }
+
+        let index = self.flush();
+        if self.total + cost > self.threshold {
+            return Err(AcceptNError::Full(self.retry_at(cost)));
+        }
+        Ok(index)
+    }
+
+    /// Attempts to accept an operation that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n) for the meaning of the errors.
+    pub fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        let index = self.check(cost)?;
+        if cost > 0 {
+            match self.buckets.back_mut() {
+                Some((last, count)) if *last == index => *count += cost,
+                _ => self.buckets.push_back((index, cost)),
+            }
+            self.total += cost;
+        }
+        Ok(())
+    }
+}
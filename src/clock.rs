@@ -0,0 +1,119 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A source of the current time for the throttles.
+pub trait Clock {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+
+    /// Returns the current wall-clock time.
+    ///
+    /// This is only used by limiters aligned to wall-clock boundaries.
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Blocks the current thread until `deadline`.
+    fn sleep_until(&self, deadline: Instant) {
+        thread::sleep(deadline.saturating_duration_since(self.now()));
+    }
+}
+
+/// The default clock, backed by `Instant::now()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when it is advanced manually.
+///
+/// Clones of a MockClock share the same time, so a clone can be passed to a throttle while the
+/// original is kept for advancing. Sleeping on a MockClock advances it to the deadline instead of
+/// blocking.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{MockClock, Throttle};
+///
+/// let clock = MockClock::new();
+/// let mut throttle = Throttle::with_clock(Duration::from_secs(4), 2, clock.clone());
+///
+/// throttle.accept().expect("The throttle is empty");
+/// clock.advance(Duration::from_secs(1));
+/// throttle.accept().expect("The throttle has one more space");
+/// throttle.accept().expect_err("The throttle should be full");
+///
+/// clock.advance(Duration::from_secs(3)); // the first accept expires now
+/// assert_eq!(throttle.size(), 1);
+/// throttle.accept().expect("The first accept should have expired");
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<(Instant, SystemTime)>>,
+}
+
+impl MockClock {
+    /// Creates a new MockClock, starting at the current instant
+    pub fn new() -> MockClock {
+        MockClock::with_system_time(SystemTime::now())
+    }
+
+    /// Creates a new MockClock, starting at the current instant with the wall-clock time `time`
+    pub fn with_system_time(time: SystemTime) -> MockClock {
+        MockClock {
+            now: Arc::new(Mutex::new((Instant::now(), time))),
+        }
+    }
+
+    /// Moves the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        now.0 += duration;
+        now.1 += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> MockClock {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.lock().unwrap().0
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.now.lock().unwrap().1
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        let mut now = self.now.lock().unwrap();
+        let (instant, system) = &mut *now;
+        if deadline > *instant {
+            *system += deadline - *instant;
+            *instant = deadline;
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Instant;
+
+use crate::{AcceptNError, Limiter};
+
+/// Composite enforces several limits at once.
+///
+/// An operation is only recorded if every component would accept it, so an operation rejected
+/// by one component does not use up the budget of the others.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{Clock, Composite, Limiter, MockClock, Throttle};
+///
+/// let clock = MockClock::new();
+/// let start = clock.now();
+/// // 2 per second AND 3 per minute
+/// let mut limit: Composite = Composite::new(vec![
+///     Box::new(Throttle::with_clock(Duration::from_secs(1), 2, clock.clone())) as Box<dyn Limiter>,
+///     Box::new(Throttle::with_clock(Duration::from_secs(60), 3, clock.clone())),
+/// ]);
+///
+/// limit.accept().expect("Both limits have space");
+/// limit.accept().expect("Both limits have space");
+/// assert_eq!(limit.accept(), Err(start + Duration::from_secs(1)));
+///
+/// clock.advance(Duration::from_secs(1));
+/// limit.accept().expect("The per-second limit has expired");
+/// // the per-second limit has space, but the per-minute limit does not
+/// assert_eq!(limit.accept(), Err(start + Duration::from_secs(60)));
+/// ```
+pub struct Composite<L = Box<dyn Limiter>> {
+    limiters: Vec<L>,
+}
+
+impl<L: Limiter> Composite<L> {
+    /// Creates a new Composite of the given limiters
+    pub fn new(limiters: Vec<L>) -> Composite<L> {
+        Composite { limiters }
+    }
+
+    /// Adds another limiter to the composite
+    pub fn push(&mut self, limiter: L) {
+        self.limiters.push(limiter);
+    }
+
+    /// Returns the component limiters
+    pub fn limiters(&self) -> &[L] {
+        &self.limiters
+    }
+
+    /// Returns the component limiters mutably
+    pub fn limiters_mut(&mut self) -> &mut [L] {
+        &mut self.limiters
+    }
+
+    /// Attempts to accept an operation in every component.
+    ///
+    /// On failure, nothing is recorded, and Err is returned with the latest Instant at which
+    /// some component is available again.
+    ///
+    /// # Panics
+    /// Panics if some component can never accept an operation.
+    pub fn accept(&mut self) -> Result<(), Instant> {
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("a Composite component has a zero limit"),
+        }
+    }
+
+    /// Checks whether every component would accept an operation that costs `cost` units.
+    ///
+    /// [`AcceptNError::TooLarge`] is returned if any component rejects the cost as too large,
+    /// otherwise the latest [`AcceptNError::Full`] among the components is returned.
+    pub fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        let mut latest = None;
+        for limiter in &mut self.limiters {
+            match limiter.check_n(cost) {
+                Ok(()) => {}
+                Err(AcceptNError::TooLarge) => return Err(AcceptNError::TooLarge),
+                Err(AcceptNError::Full(at)) => latest = latest.max(Some(at)),
+            }
+        }
+        match latest {
+            Some(at) => Err(AcceptNError::Full(at)),
+            None => Ok(()),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units in every component.
+    ///
+    /// See [`check_n`](Composite::check_n) for the meaning of the errors.
+    pub fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        self.check_n(cost)?;
+        for limiter in &mut self.limiters {
+            limiter.accept_n(cost)?;
+        }
+        Ok(())
+    }
+}
+
+impl<L: Limiter> Limiter for Composite<L> {
+    fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        Composite::check_n(self, cost)
+    }
+
+    fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        Composite::accept_n(self, cost)
+    }
+}
@@ -0,0 +1,342 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{AcceptNError, Clock, SystemClock, Throttle, ThrottleState};
+
+/// A journal of the operations accepted by a [`DurableThrottle`].
+pub trait Store {
+    /// Returns all recorded operations as wall-clock times and costs.
+    fn load(&mut self) -> io::Result<Vec<(SystemTime, usize)>>;
+
+    /// Records an accepted operation.
+    ///
+    /// The operation must be durable when this returns.
+    fn append(&mut self, time: SystemTime, cost: usize) -> io::Result<()>;
+
+    /// Replaces all recorded operations with `entries`.
+    ///
+    /// Either all or none of the changes must be durable if the process crashes.
+    fn compact(&mut self, entries: &[(SystemTime, usize)]) -> io::Result<()>;
+}
+
+/// A Store that keeps the journal in memory.
+///
+/// This does not survive restarts by itself, but is useful for testing.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    entries: Vec<(SystemTime, usize)>,
+}
+
+impl MemoryStore {
+    /// Creates a new empty MemoryStore
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn load(&mut self) -> io::Result<Vec<(SystemTime, usize)>> {
+        Ok(self.entries.clone())
+    }
+
+    fn append(&mut self, time: SystemTime, cost: usize) -> io::Result<()> {
+        self.entries.push((time, cost));
+        Ok(())
+    }
+
+    fn compact(&mut self, entries: &[(SystemTime, usize)]) -> io::Result<()> {
+        self.entries = entries.to_vec();
+        Ok(())
+    }
+}
+
+const MAGIC: &[u8; 8] = b"THRTLOG1";
+/// Seconds (u64), nanoseconds (u32), cost (u64) and checksum (u32), all little-endian
+const RECORD_LEN: usize = 24;
+
+/// A Store that keeps the journal in an append-only file.
+///
+/// Each operation is appended and synced to the file before it is accepted. Compaction writes a
+/// new file and renames it over the old one, so a crash leaves either the old or the new journal.
+/// If the process crashes during an append, the truncated record is discarded when the file is
+/// opened again.
+pub struct FileStore {
+    path: PathBuf,
+    file: File,
+}
+
+impl FileStore {
+    /// Opens or creates the journal at `path`
+    pub fn open(path: impl AsRef<Path>) -> io::Result<FileStore> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+            file.sync_all()?;
+        }
+        Ok(FileStore { path, file })
+    }
+}
+
+impl Store for FileStore {
+    fn load(&mut self) -> io::Result<Vec<(SystemTime, usize)>> {
+        let mut data = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut data)?;
+        if data.len() < MAGIC.len() && MAGIC.starts_with(&data) {
+            // the header was truncated by a crash while creating the journal
+            self.file.set_len(0)?;
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(MAGIC)?;
+            self.file.sync_all()?;
+            return Ok(Vec::new());
+        }
+        if !data.starts_with(MAGIC) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a throttle journal",
+            ));
+        }
+
+        let mut entries = Vec::new();
+        let mut valid = MAGIC.len();
+        for record in data[MAGIC.len()..].chunks(RECORD_LEN) {
+            match decode(record) {
+                Some(entry) => entries.push(entry),
+                None => break,
+            }
+            valid += RECORD_LEN;
+        }
+
+        if valid < data.len() {
+            // discard a record truncated by a crash
+            self.file.set_len(valid as u64)?;
+            self.file.sync_all()?;
+        }
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(entries)
+    }
+
+    fn append(&mut self, time: SystemTime, cost: usize) -> io::Result<()> {
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&encode(time, cost))?;
+        self.file.sync_data()
+    }
+
+    fn compact(&mut self, entries: &[(SystemTime, usize)]) -> io::Result<()> {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let mut temp = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_path)?;
+        let mut data = MAGIC.to_vec();
+        for &(time, cost) in entries {
+            data.extend_from_slice(&encode(time, cost));
+        }
+        temp.write_all(&data)?;
+        temp.sync_all()?;
+
+        fs::rename(&temp_path, &self.path)?;
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        // make the rename durable; directories cannot be synced on some platforms
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+        // keep the handle of the renamed file, so that appends cannot go to the old journal
+        self.file = temp;
+        Ok(())
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    // FNV-1a
+    data.iter().fold(0x811c_9dc5, |hash: u32, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn encode(time: SystemTime, cost: usize) -> [u8; RECORD_LEN] {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut record = [0; RECORD_LEN];
+    record[0..8].copy_from_slice(&since_epoch.as_secs().to_le_bytes());
+    record[8..12].copy_from_slice(&since_epoch.subsec_nanos().to_le_bytes());
+    record[12..20].copy_from_slice(&(cost as u64).to_le_bytes());
+    let sum = checksum(&record[0..20]);
+    record[20..24].copy_from_slice(&sum.to_le_bytes());
+    record
+}
+
+fn decode(record: &[u8]) -> Option<(SystemTime, usize)> {
+    if record.len() < RECORD_LEN {
+        return None;
+    }
+    let sum = u32::from_le_bytes(record[20..24].try_into().ok()?);
+    if checksum(&record[0..20]) != sum {
+        return None;
+    }
+    let secs = u64::from_le_bytes(record[0..8].try_into().ok()?);
+    let nanos = u32::from_le_bytes(record[8..12].try_into().ok()?);
+    let cost = u64::from_le_bytes(record[12..20].try_into().ok()?);
+    Some((UNIX_EPOCH + Duration::new(secs, nanos), cost as usize))
+}
+
+/// DurableThrottle is a [`Throttle`] whose accepted operations are journaled to a [`Store`].
+///
+/// Opening a DurableThrottle restores the unexpired operations from the store, counting the time
+/// that the process was not running as elapsed. The journal is compacted when it has grown to
+/// twice the threshold, dropping expired operations.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{DurableThrottle, FileStore};
+///
+/// let path = std::env::temp_dir().join(format!("throttle-doc-{}.log", std::process::id()));
+/// # let _ = std::fs::remove_file(&path);
+/// let mut throttle = DurableThrottle::open(Duration::from_secs(60), 2, FileStore::open(&path)?)?;
+/// throttle.accept()?.expect("The throttle is empty");
+/// drop(throttle);
+///
+/// // simulate a crash in the middle of an append
+/// use std::io::Write;
+/// std::fs::OpenOptions::new().append(true).open(&path)?.write_all(&[1, 2, 3])?;
+///
+/// let mut throttle = DurableThrottle::open(Duration::from_secs(60), 2, FileStore::open(&path)?)?;
+/// assert_eq!(throttle.size(), 1);
+/// throttle.accept()?.expect("The throttle has one more space");
+/// throttle.accept()?.expect_err("The throttle should be full");
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct DurableThrottle<S, C = SystemClock> {
+    throttle: Throttle<C>,
+    store: S,
+    /// The number of operations in the journal
+    journaled: usize,
+}
+
+impl<S: Store> DurableThrottle<S> {
+    /// Opens a DurableThrottle, restoring the operations recorded in `store`
+    pub fn open(timeout: Duration, threshold: usize, store: S) -> io::Result<DurableThrottle<S>> {
+        DurableThrottle::open_with_clock(timeout, threshold, store, SystemClock)
+    }
+}
+
+impl<S: Store, C: Clock> DurableThrottle<S, C> {
+    /// Opens a DurableThrottle that reads the time from `clock`, restoring the operations
+    /// recorded in `store`
+    pub fn open_with_clock(
+        timeout: Duration,
+        threshold: usize,
+        mut store: S,
+        clock: C,
+    ) -> io::Result<DurableThrottle<S, C>> {
+        let state = ThrottleState {
+            timeout,
+            threshold,
+            entries: store.load()?,
+        };
+        let mut durable = DurableThrottle {
+            throttle: Throttle::restore_with_clock(state, clock),
+            store,
+            journaled: 0,
+        };
+        durable.compact()?;
+        Ok(durable)
+    }
+
+    /// Drops expired operations from the journal
+    pub fn compact(&mut self) -> io::Result<()> {
+        let state = self.throttle.state();
+        self.store.compact(&state.entries)?;
+        self.journaled = state.entries.len();
+        Ok(())
+    }
+
+    /// Returns the underlying store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the number of remaining items in the throttle
+    pub fn size(&mut self) -> usize {
+        self.throttle.size()
+    }
+
+    /// Checks that the throttle is available to accept.
+    ///
+    /// See [`Throttle::available`].
+    pub fn available(&mut self) -> bool {
+        self.throttle.available()
+    }
+
+    /// Attempts to accept an operation and journal it.
+    ///
+    /// See [`Throttle::accept`]. The operation is only accepted once it has been journaled.
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn accept(&mut self) -> io::Result<Result<(), Instant>> {
+        Ok(match self.accept_n(1)? {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("DurableThrottle threshold is zero"),
+        })
+    }
+
+    /// Attempts to accept an operation that costs `cost` units of the threshold and journal it.
+    ///
+    /// See [`Throttle::accept_n`]. The operation is only accepted once it has been journaled.
+    ///
+    /// The journal is compacted automatically when it grows. If compaction fails, the accept
+    /// still succeeds and compaction is retried on the next accept; call
+    /// [`compact`](DurableThrottle::compact) to observe the error.
+    pub fn accept_n(&mut self, cost: usize) -> io::Result<Result<(), AcceptNError>> {
+        if let Err(err) = self.throttle.check_n(cost) {
+            return Ok(Err(err));
+        }
+        if cost == 0 {
+            return Ok(Ok(()));
+        }
+
+        self.store.append(self.throttle.clock.system_now(), cost)?;
+        self.journaled += 1;
+        let result = self.throttle.accept_n(cost);
+
+        if self.journaled > self.throttle.threshold.max(1) * 2 {
+            // the operation has been recorded, so a failed compaction must not reject it
+            let _ = self.compact();
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,223 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::task::Waker;
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::{Clock, SystemClock, Throttle};
+
+/// FairThrottle is a shared [`Throttle`] that admits waiting operations in arrival order.
+///
+/// Operations that cannot be accepted immediately join a queue. Only the operation at the head of
+/// the queue waits for the throttle to have room; the others wait to be woken. When the head is
+/// accepted, it wakes the next operation in the queue, so exactly one waiter is woken for each
+/// freed slot and no waiter can be overtaken by a later one.
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+/// use throttle::{FairThrottle, MockClock};
+///
+/// let clock = MockClock::new();
+/// let throttle = Arc::new(FairThrottle::with_clock(Duration::from_secs(1), 1, clock));
+/// throttle.acquire_blocking();
+///
+/// let order = Arc::new(Mutex::new(Vec::new()));
+/// let threads: Vec<_> = (0..4)
+///     .map(|i| {
+///         let thread = std::thread::spawn({
+///             let (throttle, order) = (Arc::clone(&throttle), Arc::clone(&order));
+///             move || {
+///                 throttle.acquire_blocking();
+///                 order.lock().unwrap().push(i);
+///             }
+///         });
+///         // wait for this thread to join the queue before spawning the next one
+///         while throttle.waiting() + order.lock().unwrap().len() <= i {
+///             std::thread::yield_now();
+///         }
+///         thread
+///     })
+///     .collect();
+/// for thread in threads {
+///     thread.join().unwrap();
+/// }
+/// assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3]);
+/// ```
+pub struct FairThrottle<C = SystemClock> {
+    state: Mutex<State<C>>,
+    clock: C,
+}
+
+struct State<C> {
+    throttle: Throttle<C>,
+    next_ticket: u64,
+    waiters: VecDeque<Waiter>,
+}
+
+struct Waiter {
+    ticket: u64,
+    wake: Wake,
+}
+
+#[cfg_attr(not(any(feature = "tokio", feature = "async-std")), allow(dead_code))]
+pub(crate) enum Wake {
+    Thread(Thread),
+    Task(Option<Waker>),
+}
+
+/// The result of checking the turn of a waiter.
+pub(crate) enum Turn {
+    /// The waiter has been accepted and removed from the queue.
+    Accepted,
+    /// The waiter is at the head of the queue and should retry at the Instant.
+    RetryAt(Instant),
+    /// The waiter is not at the head of the queue.
+    Queued,
+}
+
+impl FairThrottle {
+    /// Creates a new FairThrottle
+    pub fn new(timeout: Duration, threshold: usize) -> FairThrottle {
+        FairThrottle::with_clock(timeout, threshold, SystemClock)
+    }
+}
+
+impl<C: Clock + Clone> FairThrottle<C> {
+    /// Creates a new FairThrottle that reads the time from `clock`
+    pub fn with_clock(timeout: Duration, threshold: usize, clock: C) -> FairThrottle<C> {
+        FairThrottle {
+            state: Mutex::new(State {
+                throttle: Throttle::with_clock(timeout, threshold, clock.clone()),
+                next_ticket: 0,
+                waiters: VecDeque::new(),
+            }),
+            clock,
+        }
+    }
+}
+
+impl<C: Clock> FairThrottle<C> {
+    fn lock(&self) -> MutexGuard<'_, State<C>> {
+        self.state.lock().unwrap()
+    }
+
+    /// Returns the number of remaining items in the throttle
+    pub fn size(&self) -> usize {
+        self.lock().throttle.size()
+    }
+
+    /// Returns the number of operations waiting in the queue
+    pub fn waiting(&self) -> usize {
+        self.lock().waiters.len()
+    }
+
+    /// Accepts immediately if nobody is waiting, or joins the queue and returns the ticket.
+    pub(crate) fn enqueue(&self, wake: Wake) -> Option<u64> {
+        let mut state = self.lock();
+        if state.waiters.is_empty() && state.throttle.accept().is_ok() {
+            return None;
+        }
+
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiters.push_back(Waiter { ticket, wake });
+        Some(ticket)
+    }
+
+    /// Attempts to accept the waiter with `ticket` if it is at the head of the queue.
+    ///
+    /// If `waker` is given and the waiter is not at the head, it replaces the stored waker.
+    pub(crate) fn turn(&self, ticket: u64, waker: Option<&Waker>) -> Turn {
+        let mut state = self.lock();
+        let head = state.waiters.front().map(|waiter| waiter.ticket);
+        if head == Some(ticket) {
+            return match state.throttle.accept() {
+                Ok(()) => {
+                    state.waiters.pop_front();
+                    wake_head(&mut state.waiters);
+                    Turn::Accepted
+                }
+                Err(at) => Turn::RetryAt(at),
+            };
+        }
+
+        if let Some(waker) = waker {
+            let waiter = state
+                .waiters
+                .iter_mut()
+                .find(|waiter| waiter.ticket == ticket);
+            if let Some(Waiter {
+                wake: Wake::Task(stored),
+                ..
+            }) = waiter
+            {
+                *stored = Some(waker.clone());
+            }
+        }
+        Turn::Queued
+    }
+
+    /// Removes the waiter with `ticket` from the queue without accepting it.
+    #[cfg_attr(not(any(feature = "tokio", feature = "async-std")), allow(dead_code))]
+    pub(crate) fn cancel(&self, ticket: u64) {
+        let mut state = self.lock();
+        if let Some(index) = state
+            .waiters
+            .iter()
+            .position(|waiter| waiter.ticket == ticket)
+        {
+            state.waiters.remove(index);
+            if index == 0 {
+                wake_head(&mut state.waiters);
+            }
+        }
+    }
+
+    /// Blocks the current thread until an operation is accepted.
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn acquire_blocking(&self) {
+        let ticket = match self.enqueue(Wake::Thread(thread::current())) {
+            Some(ticket) => ticket,
+            None => return,
+        };
+
+        loop {
+            match self.turn(ticket, None) {
+                Turn::Accepted => return,
+                Turn::RetryAt(at) => self.clock.sleep_until(at),
+                Turn::Queued => thread::park(),
+            }
+        }
+    }
+}
+
+fn wake_head(waiters: &mut VecDeque<Waiter>) {
+    if let Some(head) = waiters.front_mut() {
+        match &mut head.wake {
+            Wake::Thread(thread) => thread.unpark(),
+            Wake::Task(waker) => {
+                if let Some(waker) = waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{nanos_to_duration, AcceptNError, Clock, SystemClock};
+
+/// FixedWindow is a counter that resets at fixed wall-clock boundaries.
+///
+/// Windows of length `window` start at every multiple of `window` after the Unix epoch, shifted
+/// by `offset`. For example, a window of one day with zero offset resets at every midnight UTC,
+/// and an offset of 16 hours resets at every midnight in UTC+8.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use throttle::{FixedWindow, MockClock};
+///
+/// let minute = Duration::from_secs(60);
+/// let clock = MockClock::with_system_time(UNIX_EPOCH + minute * 1000 + Duration::from_secs(50));
+/// let mut window = FixedWindow::with_clock(minute, 2, Duration::from_secs(0), clock.clone());
+///
+/// window.accept().expect("The window is empty");
+/// window.accept().expect("The window has one more space");
+/// assert_eq!(window.remaining(), 0);
+/// assert_eq!(window.reset_time(), UNIX_EPOCH + minute * 1001);
+/// window.accept().expect_err("The window should be full");
+///
+/// clock.advance(Duration::from_secs(10)); // the next minute starts
+/// assert_eq!(window.remaining(), 2);
+/// window.accept().expect("The window has been reset");
+/// ```
+pub struct FixedWindow<C = SystemClock> {
+    window: Duration,
+    threshold: usize,
+    offset: Duration,
+    /// The index of the current window since the shifted epoch
+    index: i128,
+    count: usize,
+    clock: C,
+}
+
+impl FixedWindow {
+    /// Creates a new FixedWindow
+    ///
+    /// # Panics
+    /// Panics if `window` is zero.
+    pub fn new(window: Duration, threshold: usize, offset: Duration) -> FixedWindow {
+        FixedWindow::with_clock(window, threshold, offset, SystemClock)
+    }
+}
+
+impl<C: Clock> FixedWindow<C> {
+    /// Creates a new FixedWindow that reads the time from `clock`
+    ///
+    /// # Panics
+    /// Panics if `window` is zero.
+    pub fn with_clock(
+        window: Duration,
+        threshold: usize,
+        offset: Duration,
+        clock: C,
+    ) -> FixedWindow<C> {
+        assert!(window > Duration::from_secs(0), "window must be positive");
+        let mut ret = FixedWindow {
+            window,
+            threshold,
+            offset,
+            index: 0,
+            count: 0,
+            clock,
+        };
+        ret.index = ret.current_index();
+        ret
+    }
+
+    fn current_index(&self) -> i128 {
+        let since_epoch = match self.clock.system_now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_nanos() as i128,
+            Err(err) => -(err.duration().as_nanos() as i128),
+        };
+        (since_epoch - self.offset.as_nanos() as i128).div_euclid(self.window.as_nanos() as i128)
+    }
+
+    fn flush(&mut self) {
+        let index = self.current_index();
+        if index != self.index {
+            self.index = index;
+            self.count = 0;
+        }
+    }
+
+    /// Returns the number of operations accepted in the current window
+    pub fn size(&mut self) -> usize {
+        self.flush();
+        self.count
+    }
+
+    /// Returns the number of operations that can still be accepted in the current window
+    pub fn remaining(&mut self) -> usize {
+        self.threshold.saturating_sub(self.size())
+    }
+
+    /// Returns the wall-clock time at which the current window ends
+    pub fn reset_time(&mut self) -> SystemTime {
+        self.flush();
+        let end =
+            (self.index + 1) * self.window.as_nanos() as i128 + self.offset.as_nanos() as i128;
+        if end >= 0 {
+            UNIX_EPOCH + nanos_to_duration(end as u128)
+        } else {
+            UNIX_EPOCH - nanos_to_duration(end.unsigned_abs())
+        }
+    }
+
+    /// Returns the Instant at which the current window ends
+    pub fn reset_at(&mut self) -> Instant {
+        let reset = self.reset_time();
+        let now = self.clock.now();
+        now + reset
+            .duration_since(self.clock.system_now())
+            .unwrap_or_default()
+    }
+
+    /// Checks that the window is available to accept.
+    ///
+    /// The same race conditions as [`Throttle::available`](crate::Throttle::available) apply.
+    pub fn available(&mut self) -> bool {
+        self.remaining() > 0
+    }
+
+    /// Attempts to accept an operation in the current window.
+    ///
+    /// On failure, Err is returned with an Instant indicating the time that the window resets.
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn accept(&mut self) -> Result<(), Instant> {
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("FixedWindow threshold is zero"),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n) for the meaning of the errors.
+    pub fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        self.check_n(cost)?;
+        self.count += cost;
+        Ok(())
+    }
+
+    /// Checks whether an operation that costs `cost` units of the threshold would be accepted
+    /// now, without recording it.
+    ///
+    /// The errors are the same as [`accept_n`](FixedWindow::accept_n).
+    pub fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        if cost > self.threshold {
+            return Err(AcceptNError::TooLarge);
+        }
+
+        if self.remaining() < cost {
+            return Err(AcceptNError::Full(self.reset_at()));
+        }
+        Ok(())
+    }
+}
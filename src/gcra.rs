@@ -0,0 +1,167 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use crate::{nanos_to_duration, AcceptNError, Clock, SystemClock};
+
+/// Gcra is a rate limiter implementing the generic cell rate algorithm.
+///
+/// Like [`Throttle`](crate::Throttle), it admits `threshold` operations per `timeout`, in bursts
+/// of up to `threshold`. Instead of remembering every accepted operation, it only stores the
+/// theoretical arrival time of the next operation, so its memory usage is constant.
+///
+/// Unlike `Throttle`, the capacity freed by earlier operations is spread evenly over `timeout`.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{Clock, Gcra, MockClock};
+///
+/// let clock = MockClock::new();
+/// let mut gcra = Gcra::with_clock(Duration::from_secs(4), 2, clock.clone());
+///
+/// gcra.accept().expect("The limiter is empty");
+/// gcra.accept().expect("The burst allows two operations");
+/// assert_eq!(gcra.size(), 2);
+/// // one operation is emitted every 2 seconds
+/// assert_eq!(gcra.accept(), Err(clock.now() + Duration::from_secs(2)));
+///
+/// clock.advance(Duration::from_secs(2));
+/// assert_eq!(gcra.size(), 1);
+/// gcra.accept().expect("One operation has been emitted");
+/// gcra.accept().expect_err("The limiter should be full");
+/// ```
+pub struct Gcra<C = SystemClock> {
+    timeout: Duration,
+    threshold: usize,
+    /// The time to emit one operation, in nanoseconds
+    interval: u128,
+    tat: Instant,
+    clock: C,
+}
+
+impl Gcra {
+    /// Creates a new Gcra
+    ///
+    /// # Panics
+    /// Panics if `threshold` is zero or exceeds the number of nanoseconds in `timeout`, or if
+    /// `timeout` is too long to compute deadlines as Instants.
+    pub fn new(timeout: Duration, threshold: usize) -> Gcra {
+        Gcra::with_clock(timeout, threshold, SystemClock)
+    }
+}
+
+impl<C: Clock> Gcra<C> {
+    /// Creates a new Gcra that reads the time from `clock`
+    ///
+    /// # Panics
+    /// Panics if `threshold` is zero or exceeds the number of nanoseconds in `timeout`, or if
+    /// `timeout` is too long to compute deadlines as Instants.
+    pub fn with_clock(timeout: Duration, threshold: usize, clock: C) -> Gcra<C> {
+        assert!(threshold > 0, "threshold must be positive");
+        assert!(
+            timeout.as_nanos() >= threshold as u128,
+            "timeout must be at least threshold nanoseconds"
+        );
+        // the theoretical arrival time is at most `timeout` ahead, and a check adds up to `timeout`
+        let now = clock.now();
+        assert!(
+            now.checked_add(timeout)
+                .and_then(|tat| tat.checked_add(timeout))
+                .is_some(),
+            "timeout is too long"
+        );
+        Gcra {
+            timeout,
+            threshold,
+            interval: timeout.as_nanos() / threshold as u128,
+            tat: now,
+            clock,
+        }
+    }
+
+    /// Continues from the operations accepted by `previous`, which may have other parameters.
+    #[cfg_attr(not(feature = "resp"), allow(dead_code))]
+    pub(crate) fn resume(&mut self, previous: &Gcra<C>) {
+        self.tat = previous.tat;
+    }
+
+    /// Returns the Instant at which all accepted operations have been emitted
+    pub fn reset_at(&self) -> Instant {
+        self.tat.max(self.clock.now())
+    }
+
+    /// Returns the number of operations that have not been emitted yet
+    pub fn size(&mut self) -> usize {
+        let backlog = self
+            .tat
+            .saturating_duration_since(self.clock.now())
+            .as_nanos();
+        backlog.div_ceil(self.interval) as usize
+    }
+
+    /// Checks that the limiter is available to accept.
+    ///
+    /// The same race conditions as [`Throttle::available`](crate::Throttle::available) apply.
+    pub fn available(&mut self) -> bool {
+        let now = self.clock.now();
+        let next = self.tat.max(now) + nanos_to_duration(self.interval);
+        next - now <= self.timeout
+    }
+
+    /// Attempts to accept an operation.
+    ///
+    /// On failure, Err is returned with an Instant indicating the time that the limiter is
+    /// available again.
+    pub fn accept(&mut self) -> Result<(), Instant> {
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => unreachable!("threshold is positive"),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n) for the meaning of the errors.
+    pub fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        self.tat = self.check(cost)?;
+        Ok(())
+    }
+
+    /// Checks whether an operation that costs `cost` units of the threshold would be accepted
+    /// now, without recording it.
+    ///
+    /// The errors are the same as [`accept_n`](Gcra::accept_n).
+    pub fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        self.check(cost).map(|_| ())
+    }
+
+    /// Checks `cost` and returns the theoretical arrival time after accepting it.
+    fn check(&self, cost: usize) -> Result<Instant, AcceptNError> {
+        if cost > self.threshold {
+            return Err(AcceptNError::TooLarge);
+        }
+
+        let now = self.clock.now();
+        let tat = self.tat.max(now) + nanos_to_duration(self.interval * cost as u128);
+        if let Some(allow_at) = tat.checked_sub(self.timeout) {
+            if allow_at > now {
+                return Err(AcceptNError::Full(allow_at));
+            }
+        }
+        Ok(tat)
+    }
+}
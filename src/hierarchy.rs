@@ -0,0 +1,178 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use crate::{AcceptNError, Limiter};
+
+/// Identifies a node in a [`Hierarchy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// Hierarchy is a tree of limiters, where each node also draws from the limits of its ancestors.
+///
+/// For example, each user may be a child of its tenant, so that an operation of a user is only
+/// accepted if both the user and the tenant have room, and is then recorded in both.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{Hierarchy, MockClock, Throttle};
+///
+/// let clock = MockClock::new();
+/// let minute = Duration::from_secs(60);
+/// let mut tree = Hierarchy::new();
+/// let tenant = tree.add_root(Throttle::with_clock(minute, 3, clock.clone()));
+/// let alice = tree.add_child(tenant, Throttle::with_clock(minute, 2, clock.clone()));
+/// let bob = tree.add_child(tenant, Throttle::with_clock(minute, 2, clock.clone()));
+///
+/// tree.accept(alice).expect("alice and the tenant have room");
+/// tree.accept(alice).expect("alice and the tenant have room");
+/// assert_eq!(tree.accept(alice).unwrap_err().node, alice);
+///
+/// tree.accept(bob).expect("bob and the tenant have room");
+/// assert_eq!(tree.accept(bob).unwrap_err().node, tenant);
+/// ```
+pub struct Hierarchy<L = Box<dyn Limiter>> {
+    nodes: Vec<Node<L>>,
+}
+
+struct Node<L> {
+    parent: Option<NodeId>,
+    limiter: L,
+}
+
+impl<L: Limiter> Hierarchy<L> {
+    /// Creates a new empty Hierarchy
+    pub fn new() -> Hierarchy<L> {
+        Hierarchy { nodes: Vec::new() }
+    }
+
+    fn add(&mut self, parent: Option<NodeId>, limiter: L) -> NodeId {
+        self.nodes.push(Node { parent, limiter });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Adds a node without a parent
+    pub fn add_root(&mut self, limiter: L) -> NodeId {
+        self.add(None, limiter)
+    }
+
+    /// Adds a node under `parent`
+    ///
+    /// # Panics
+    /// Panics if `parent` is not in this hierarchy.
+    pub fn add_child(&mut self, parent: NodeId, limiter: L) -> NodeId {
+        assert!(
+            parent.0 < self.nodes.len(),
+            "parent is not in this hierarchy"
+        );
+        self.add(Some(parent), limiter)
+    }
+
+    /// Returns the parent of `node`
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.nodes[node.0].parent
+    }
+
+    /// Returns the limiter of `node`
+    pub fn limiter(&self, node: NodeId) -> &L {
+        &self.nodes[node.0].limiter
+    }
+
+    /// Returns the limiter of `node` mutably
+    pub fn limiter_mut(&mut self, node: NodeId) -> &mut L {
+        &mut self.nodes[node.0].limiter
+    }
+
+    /// Returns `node` followed by its ancestors, from the nearest to the root
+    fn path(&self, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(Some(node), move |&node| self.parent(node))
+    }
+
+    /// Attempts to accept an operation at `node`.
+    ///
+    /// See [`accept_n`](Hierarchy::accept_n).
+    pub fn accept(&mut self, node: NodeId) -> Result<(), Denied> {
+        self.accept_n(node, 1)
+    }
+
+    /// Checks whether `node` and all its ancestors would accept an operation that costs `cost`
+    /// units.
+    ///
+    /// If several levels deny the operation, the one that can accept it the latest is reported,
+    /// and a level that can never accept it takes precedence.
+    pub fn check_n(&mut self, node: NodeId, cost: usize) -> Result<(), Denied> {
+        let mut denied: Option<Denied> = None;
+        let path: Vec<_> = self.path(node).collect();
+        for id in path {
+            let error = match self.nodes[id.0].limiter.check_n(cost) {
+                Ok(()) => continue,
+                Err(error) => error,
+            };
+            let later = match (denied.map(|denied| denied.error), error) {
+                (None, _) => true,
+                (Some(AcceptNError::TooLarge), _) => false,
+                (Some(_), AcceptNError::TooLarge) => true,
+                (Some(AcceptNError::Full(prev)), AcceptNError::Full(at)) => at > prev,
+            };
+            if later {
+                denied = Some(Denied { node: id, error });
+            }
+        }
+        match denied {
+            Some(denied) => Err(denied),
+            None => Ok(()),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units at `node`.
+    ///
+    /// The operation is only recorded if `node` and all its ancestors accept it, in which case it
+    /// is recorded in all of them. See [`check_n`](Hierarchy::check_n) for the error.
+    pub fn accept_n(&mut self, node: NodeId, cost: usize) -> Result<(), Denied> {
+        self.check_n(node, cost)?;
+        let path: Vec<_> = self.path(node).collect();
+        for id in path {
+            self.nodes[id.0]
+                .limiter
+                .accept_n(cost)
+                .map_err(|error| Denied { node: id, error })?;
+        }
+        Ok(())
+    }
+}
+
+impl<L: Limiter> Default for Hierarchy<L> {
+    fn default() -> Hierarchy<L> {
+        Hierarchy::new()
+    }
+}
+
+/// The error returned when a [`Hierarchy`] rejects an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Denied {
+    /// The node whose limiter rejected the operation
+    pub node: NodeId,
+    /// The error from the limiter of `node`
+    pub error: AcceptNError,
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at node {}", self.error, self.node.0)
+    }
+}
+
+impl std::error::Error for Denied {}
@@ -0,0 +1,194 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use crate::{AcceptNError, Clock, SystemClock, TokenBucket};
+
+/// Identifies a class in an [`Htb`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClassId(usize);
+
+/// Htb shares a parent budget between sibling classes, in the style of Linux HTB.
+///
+/// Each class is guaranteed `rate` operations per `period`, and may borrow unused capacity of the
+/// parent up to a total of `ceil` operations per `period`. Guaranteed operations are always
+/// accepted regardless of the parent budget, but are still charged to it: if the parent budget
+/// has been borrowed already, it goes into debt and no class can borrow until the debt is
+/// repaid. Borrowing classes can therefore only use capacity that the other classes leave idle.
+/// For the guarantees to hold, the parent rate should be at least the sum of the guaranteed
+/// rates.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{Htb, MockClock};
+///
+/// let clock = MockClock::new();
+/// let mut htb = Htb::with_clock(10, Duration::from_secs(1), clock.clone());
+/// let interactive = htb.add_class(6, 10);
+/// let background = htb.add_class(2, 10);
+///
+/// // background jobs take their guaranteed share and borrow the idle capacity
+/// for _ in 0..10 {
+///     htb.accept(background).expect("The parent is idle");
+/// }
+/// htb.accept(background).expect_err("The ceiling has been reached");
+///
+/// // interactive traffic still gets its guaranteed share, but cannot borrow any more
+/// for _ in 0..6 {
+///     htb.accept(interactive).expect("The guaranteed rate is available");
+/// }
+/// htb.accept(interactive).expect_err("The parent budget has been used up");
+///
+/// // the guaranteed operations put the parent into debt, which is repaid before borrowing resumes
+/// clock.advance(Duration::from_secs(1));
+/// for _ in 0..6 {
+///     htb.accept(interactive).expect("The guaranteed rate is available");
+/// }
+/// let sent = (0..10).filter(|_| htb.accept(background).is_ok()).count();
+/// assert_eq!(sent, 2, "background jobs only get their guaranteed share");
+/// ```
+pub struct Htb<C = SystemClock> {
+    period: Duration,
+    parent: TokenBucket<C>,
+    classes: Vec<Class<C>>,
+    clock: C,
+}
+
+struct Class<C> {
+    /// The guaranteed rate, or None if nothing is guaranteed
+    assured: Option<TokenBucket<C>>,
+    /// The ceiling rate, or None if the ceiling is zero
+    ceil: Option<TokenBucket<C>>,
+}
+
+impl Htb {
+    /// Creates a new Htb with a parent budget of `rate` operations per `period`
+    ///
+    /// # Panics
+    /// Panics if `rate` or `period` is zero.
+    pub fn new(rate: usize, period: Duration) -> Htb {
+        Htb::with_clock(rate, period, SystemClock)
+    }
+}
+
+impl<C: Clock + Clone> Htb<C> {
+    /// Creates a new Htb with a parent budget of `rate` operations per `period` that reads the
+    /// time from `clock`
+    ///
+    /// # Panics
+    /// Panics if `rate` or `period` is zero.
+    pub fn with_clock(rate: usize, period: Duration, clock: C) -> Htb<C> {
+        Htb {
+            period,
+            parent: TokenBucket::with_clock(rate, rate, period, clock.clone()),
+            classes: Vec::new(),
+            clock,
+        }
+    }
+
+    fn bucket(&self, rate: usize) -> Option<TokenBucket<C>> {
+        if rate == 0 {
+            return None;
+        }
+        Some(TokenBucket::with_clock(
+            rate,
+            rate,
+            self.period,
+            self.clock.clone(),
+        ))
+    }
+
+    /// Adds a class guaranteed `rate` operations per period, which may borrow up to a total of
+    /// `ceil` operations per period
+    ///
+    /// # Panics
+    /// Panics if `ceil` is less than `rate`.
+    pub fn add_class(&mut self, rate: usize, ceil: usize) -> ClassId {
+        assert!(ceil >= rate, "ceil must not be less than rate");
+        let class = Class {
+            assured: self.bucket(rate),
+            ceil: self.bucket(ceil),
+        };
+        self.classes.push(class);
+        ClassId(self.classes.len() - 1)
+    }
+
+    /// Attempts to accept an operation for `class`.
+    ///
+    /// On failure, Err is returned with an Instant indicating the time that the class can send
+    /// again, either from its guaranteed rate or by borrowing.
+    ///
+    /// # Panics
+    /// Panics if the ceiling of `class` is zero.
+    pub fn accept(&mut self, class: ClassId) -> Result<(), Instant> {
+        match self.accept_n(class, 1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("Htb class ceiling is zero"),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units for `class`.
+    ///
+    /// The operation is taken from the guaranteed rate if possible, otherwise it is borrowed
+    /// from the parent as a whole. [`AcceptNError::TooLarge`] is returned if `cost` exceeds both
+    /// the guaranteed rate and what can be borrowed.
+    pub fn accept_n(&mut self, class: ClassId, cost: usize) -> Result<(), AcceptNError> {
+        let Class { assured, ceil } = &mut self.classes[class.0];
+
+        let assured_check = match assured {
+            Some(assured) => assured.check_n(cost),
+            None => Err(AcceptNError::TooLarge),
+        };
+        if assured_check.is_ok() {
+            if let Some(assured) = assured {
+                assured.accept_n(cost)?;
+            }
+            if let Some(ceil) = ceil {
+                ceil.force_n(cost);
+            }
+            self.parent.force_n(cost);
+            return Ok(());
+        }
+
+        let ceil = match ceil {
+            Some(ceil) => ceil,
+            None => return assured_check,
+        };
+        let borrow_check = match (ceil.check_n(cost), self.parent.check_n(cost)) {
+            (Ok(()), result) | (result, Ok(())) => result,
+            (Err(AcceptNError::Full(a)), Err(AcceptNError::Full(b))) => {
+                Err(AcceptNError::Full(a.max(b)))
+            }
+            _ => Err(AcceptNError::TooLarge),
+        };
+        if borrow_check.is_ok() {
+            ceil.accept_n(cost)?;
+            self.parent.accept_n(cost)?;
+            return Ok(());
+        }
+
+        match (assured_check, borrow_check) {
+            (Err(AcceptNError::Full(a)), Err(AcceptNError::Full(b))) => {
+                Err(AcceptNError::Full(a.min(b)))
+            }
+            (Err(AcceptNError::Full(at)), _) | (_, Err(AcceptNError::Full(at))) => {
+                Err(AcceptNError::Full(at))
+            }
+            _ => Err(AcceptNError::TooLarge),
+        }
+    }
+}
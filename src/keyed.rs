@@ -0,0 +1,160 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use crate::{AcceptNError, Clock, SystemClock, Throttle};
+
+/// KeyedThrottle is a set of [`Throttle`]s with the same parameters, one for each key.
+///
+/// Throttles are created when a key is first accepted, and removed once they become empty, so
+/// memory usage only grows with the number of recently active keys.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{KeyedThrottle, MockClock};
+///
+/// let clock = MockClock::new();
+/// let mut throttle = KeyedThrottle::with_clock(Duration::from_secs(10), 1, clock.clone());
+///
+/// throttle.accept("alice").expect("alice has not been throttled");
+/// throttle.accept("bob").expect("bob has not been throttled");
+/// throttle.accept("alice").expect_err("alice should be throttled");
+/// assert_eq!(throttle.len(), 2);
+///
+/// clock.advance(Duration::from_secs(10));
+/// assert_eq!(throttle.size("alice"), 0);
+/// throttle.flush();
+/// assert_eq!(throttle.len(), 0);
+/// ```
+pub struct KeyedThrottle<K, C = SystemClock> {
+    timeout: Duration,
+    threshold: usize,
+    throttles: HashMap<K, Throttle<C>>,
+    /// The number of keys after the last full flush
+    flushed_len: usize,
+    clock: C,
+}
+
+impl<K: Hash + Eq> KeyedThrottle<K> {
+    /// Creates a new KeyedThrottle
+    pub fn new(timeout: Duration, threshold: usize) -> KeyedThrottle<K> {
+        KeyedThrottle::with_clock(timeout, threshold, SystemClock)
+    }
+}
+
+impl<K: Hash + Eq, C: Clock + Clone> KeyedThrottle<K, C> {
+    /// Creates a new KeyedThrottle that reads the time from `clock`
+    pub fn with_clock(timeout: Duration, threshold: usize, clock: C) -> KeyedThrottle<K, C> {
+        KeyedThrottle {
+            timeout,
+            threshold,
+            throttles: HashMap::new(),
+            flushed_len: 0,
+            clock,
+        }
+    }
+
+    /// Returns the number of tracked keys, which may include keys that have become empty since
+    /// the last flush
+    pub fn len(&self) -> usize {
+        self.throttles.len()
+    }
+
+    /// Returns true if no keys are tracked
+    pub fn is_empty(&self) -> bool {
+        self.throttles.is_empty()
+    }
+
+    /// Removes the throttles of all keys that have become empty.
+    ///
+    /// This is called automatically when the number of keys has doubled since the last flush,
+    /// so the amortized cost per accept is constant.
+    pub fn flush(&mut self) {
+        self.throttles.retain(|_, throttle| throttle.size() > 0);
+        self.flushed_len = self.throttles.len();
+    }
+
+    /// Returns the number of remaining items in the throttle of `key`
+    pub fn size<Q>(&mut self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let size = match self.throttles.get_mut(key) {
+            Some(throttle) => throttle.size(),
+            None => return 0,
+        };
+        if size == 0 {
+            self.throttles.remove(key);
+        }
+        size
+    }
+
+    /// Checks that the throttle of `key` is available to accept.
+    ///
+    /// See [`Throttle::available`].
+    pub fn available<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.size(key) < self.threshold
+    }
+
+    /// Attempts to accept an operation for `key`.
+    ///
+    /// See [`Throttle::accept`].
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn accept<Q>(&mut self, key: &Q) -> Result<(), Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        match self.accept_n(key, 1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("KeyedThrottle threshold is zero"),
+        }
+    }
+
+    /// Attempts to accept an operation for `key` that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`].
+    pub fn accept_n<Q>(&mut self, key: &Q, cost: usize) -> Result<(), AcceptNError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some(throttle) = self.throttles.get_mut(key) {
+            return throttle.accept_n(cost);
+        }
+
+        let mut throttle = Throttle::with_clock(self.timeout, self.threshold, self.clock.clone());
+        throttle.accept_n(cost)?;
+        if cost > 0 {
+            self.throttles.insert(key.to_owned(), throttle);
+            if self.throttles.len() > self.flushed_len.max(1) * 2 {
+                self.flush();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use crate::{nanos_to_duration, Clock, SystemClock};
+
+/// LeakyBucket is a traffic shaper that delays operations instead of rejecting them.
+///
+/// Operations leave the bucket one per `interval`. Each submitted operation is given the earliest
+/// Instant at which it may run, so that operations run at an even rate. At most `depth`
+/// operations may be waiting at a time; further submissions are rejected.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{Clock, LeakyBucket, MockClock};
+///
+/// let clock = MockClock::new();
+/// let start = clock.now();
+/// let mut bucket = LeakyBucket::with_clock(Duration::from_secs(1), 2, clock.clone());
+///
+/// assert_eq!(bucket.schedule(), Ok(start));
+/// assert_eq!(bucket.schedule(), Ok(start + Duration::from_secs(1)));
+/// assert_eq!(bucket.schedule(), Ok(start + Duration::from_secs(2)));
+/// assert_eq!(bucket.size(), 2);
+/// // two operations are already waiting
+/// assert_eq!(bucket.schedule(), Err(start + Duration::from_secs(1)));
+///
+/// clock.advance(Duration::from_secs(1));
+/// assert_eq!(bucket.size(), 1);
+/// assert_eq!(bucket.delay(), Ok(Duration::from_secs(2)));
+/// ```
+pub struct LeakyBucket<C = SystemClock> {
+    interval: Duration,
+    depth: usize,
+    /// The earliest Instant at which the next operation may run
+    next: Instant,
+    clock: C,
+}
+
+impl LeakyBucket {
+    /// Creates a new empty LeakyBucket
+    pub fn new(interval: Duration, depth: usize) -> LeakyBucket {
+        LeakyBucket::with_clock(interval, depth, SystemClock)
+    }
+}
+
+impl<C: Clock> LeakyBucket<C> {
+    /// Creates a new empty LeakyBucket that reads the time from `clock`
+    pub fn with_clock(interval: Duration, depth: usize, clock: C) -> LeakyBucket<C> {
+        LeakyBucket {
+            interval,
+            depth,
+            next: clock.now(),
+            clock,
+        }
+    }
+
+    /// Returns the number of scheduled operations that are still waiting to run
+    pub fn size(&mut self) -> usize {
+        let backlog = self
+            .next
+            .saturating_duration_since(self.clock.now())
+            .as_nanos();
+        let interval = self.interval.as_nanos();
+        if backlog == 0 || interval == 0 {
+            return 0;
+        }
+        backlog.div_ceil(interval) as usize - 1
+    }
+
+    /// Checks that the bucket can schedule another operation.
+    ///
+    /// The same race conditions as [`Throttle::available`](crate::Throttle::available) apply.
+    pub fn available(&mut self) -> bool {
+        self.next <= self.clock.now() || self.size() < self.depth
+    }
+
+    /// Schedules an operation.
+    ///
+    /// On success, Ok is returned with the Instant at which the operation may run.
+    ///
+    /// On failure, Err is returned with an Instant indicating the time that the queue has room
+    /// again.
+    pub fn schedule(&mut self) -> Result<Instant, Instant> {
+        let now = self.clock.now();
+        if !self.available() {
+            let drain = nanos_to_duration(self.interval.as_nanos() * self.depth as u128);
+            return Err(self.next - drain);
+        }
+
+        let at = self.next.max(now);
+        self.next = at + self.interval;
+        Ok(at)
+    }
+
+    /// Schedules an operation, returning the time to wait before it may run.
+    ///
+    /// On failure, Err is returned with the time to wait before the queue has room again.
+    pub fn delay(&mut self) -> Result<Duration, Duration> {
+        let now = self.clock.now();
+        match self.schedule() {
+            Ok(at) => Ok(at - now),
+            Err(at) => Err(at - now),
+        }
+    }
+}
@@ -14,8 +14,87 @@
 // limitations under the License.
 
 use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
 use std::time::{Duration, Instant};
 
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+mod acquire;
+
+mod atomic;
+pub use atomic::AtomicGcra;
+
+mod bounded;
+pub use bounded::{BoundedKeyedThrottle, Eviction};
+
+mod bucketed;
+pub use bucketed::BucketedThrottle;
+
+mod clock;
+pub use clock::{Clock, MockClock, SystemClock};
+
+mod composite;
+pub use composite::Composite;
+
+mod durable;
+pub use durable::{DurableThrottle, FileStore, MemoryStore, Store};
+
+mod fair;
+pub use fair::FairThrottle;
+
+mod fixed_window;
+pub use fixed_window::FixedWindow;
+
+mod gcra;
+pub use gcra::Gcra;
+
+mod hierarchy;
+pub use hierarchy::{Denied, Hierarchy, NodeId};
+
+mod htb;
+pub use htb::{ClassId, Htb};
+
+mod keyed;
+pub use keyed::KeyedThrottle;
+
+mod leaky_bucket;
+pub use leaky_bucket::LeakyBucket;
+
+mod limiter;
+pub use limiter::Limiter;
+
+#[cfg(any(feature = "server", feature = "resp"))]
+mod net;
+
+mod rate;
+pub use rate::{ParseRateError, ParseRateErrorKind, Rate};
+
+#[cfg(feature = "resp")]
+pub mod resp;
+
+#[cfg(feature = "shared")]
+mod shared;
+#[cfg(feature = "shared")]
+pub use shared::SharedThrottle;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+mod sharded;
+pub use sharded::ShardedThrottle;
+
+mod sliding_window;
+pub use sliding_window::SlidingWindow;
+
+mod state;
+pub use state::ThrottleState;
+
+mod sync;
+pub use sync::SyncThrottle;
+
+mod token_bucket;
+pub use token_bucket::TokenBucket;
+
 /// Throttle is a simple utility for rate-limiting operations.
 ///
 /// ```
@@ -50,36 +129,68 @@ use std::time::{Duration, Instant};
 /// std::thread::sleep(unit * 10); // time is now +10t, and all accepts should have expired
 /// assert_eq!(throttle.size(), 0);
 /// ```
-pub struct Throttle {
+///
+/// The time source can be replaced with [`Throttle::with_clock`], e.g. with a [`MockClock`] to
+/// avoid sleeping in tests.
+pub struct Throttle<C = SystemClock> {
     timeout: Duration,
     threshold: usize,
-    deque: VecDeque<Instant>,
+    deque: VecDeque<(Instant, usize)>,
+    total: usize,
+    clock: C,
 }
 
 impl Throttle {
     /// Creates a new Throttle
     pub fn new(timeout: Duration, threshold: usize) -> Throttle {
+        Throttle::with_clock(timeout, threshold, SystemClock)
+    }
+}
+
+impl<C: Clock> Throttle<C> {
+    /// Creates a new Throttle that reads the time from `clock`
+    pub fn with_clock(timeout: Duration, threshold: usize, clock: C) -> Throttle<C> {
         Throttle {
             timeout,
             threshold,
             deque: Default::default(),
+            total: 0,
+            clock,
         }
     }
 
     fn flush(&mut self) {
-        while let Some(first) = self.deque.front() {
-            if first.elapsed() >= self.timeout.clone() {
+        let now = self.clock.now();
+        while let Some(&(first, cost)) = self.deque.front() {
+            if now.saturating_duration_since(first) >= self.timeout {
                 self.deque.pop_front();
+                self.total -= cost;
             } else {
                 break;
             }
         }
     }
 
-    /// Returns the number of remaining items in the throttle
+    /// Returns the earliest time at which `cost` more units fit into the throttle.
+    ///
+    /// `cost` must not exceed the threshold.
+    fn retry_at(&self, cost: usize) -> Instant {
+        let mut remaining = self.total;
+        for &(time, entry_cost) in &self.deque {
+            remaining -= entry_cost;
+            if remaining + cost <= self.threshold {
+                return time + self.timeout;
+            }
+        }
+        unreachable!("cost exceeds threshold")
+    }
+
+    /// Returns the number of remaining items in the throttle.
+    ///
+    /// Operations accepted with [`accept_n`](Throttle::accept_n) count as their cost.
     pub fn size(&mut self) -> usize {
         self.flush();
-        self.deque.len()
+        self.total
     }
 
     /// Checks that the throttle is availbale to accept.
@@ -97,13 +208,144 @@ impl Throttle {
     ///
     /// On failure, Err is returned with an Instant indicating the time that the throttle is
     /// available again.
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
     pub fn accept(&mut self) -> Result<(), Instant> {
-        self.flush();
-        if self.deque.len() >= self.threshold {
-            return Err(self.deque.front().unwrap().clone() + self.timeout.clone());
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("Throttle threshold is zero"),
         }
+    }
 
-        self.deque.push_back(Instant::now());
+    /// Attempts to accept an operation that costs `cost` units of the threshold.
+    ///
+    /// On success, Ok is returned and the counter increments by `cost`.
+    ///
+    /// On failure, the counter is unchanged. [`AcceptNError::Full`] indicates the time at which
+    /// enough earlier operations have expired for `cost` to fit, and [`AcceptNError::TooLarge`]
+    /// indicates that `cost` exceeds the threshold and can never be accepted.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use throttle::{AcceptNError, Clock, MockClock, Throttle};
+    ///
+    /// let clock = MockClock::new();
+    /// let mut throttle = Throttle::with_clock(Duration::from_secs(10), 100, clock.clone());
+    ///
+    /// throttle.accept_n(50).expect("The throttle is empty");
+    /// clock.advance(Duration::from_secs(1));
+    /// throttle.accept_n(30).expect("The throttle has 50 more units");
+    /// assert_eq!(throttle.size(), 80);
+    ///
+    /// // 30 more units only fit after the first 50 expire
+    /// assert_eq!(throttle.accept_n(30), Err(AcceptNError::Full(clock.now() + Duration::from_secs(9))));
+    /// assert_eq!(throttle.accept_n(101), Err(AcceptNError::TooLarge));
+    /// assert_eq!(throttle.size(), 80);
+    /// ```
+    pub fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        self.check_n(cost)?;
+        if cost > 0 {
+            self.deque.push_back((self.clock.now(), cost));
+            self.total += cost;
+        }
+        Ok(())
+    }
+
+    /// Checks whether an operation that costs `cost` units of the threshold would be accepted
+    /// now, without recording it.
+    ///
+    /// The errors are the same as [`accept_n`](Throttle::accept_n).
+    pub fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        if cost > self.threshold {
+            return Err(AcceptNError::TooLarge);
+        }
+
+        self.flush();
+        if self.total + cost > self.threshold {
+            return Err(AcceptNError::Full(self.retry_at(cost)));
+        }
         Ok(())
     }
+
+    /// Blocks the current thread until an operation is accepted.
+    ///
+    /// This sleeps until the Instant returned by [`accept`](Throttle::accept) and retries.
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn acquire_blocking(&mut self) {
+        while let Err(at) = self.accept() {
+            self.clock.sleep_until(at);
+        }
+    }
+
+    /// Blocks the current thread until an operation is accepted, or gives up if it cannot be
+    /// accepted within `timeout`.
+    ///
+    /// On failure, Err is returned immediately without sleeping, with the Instant at which the
+    /// throttle is available again. A `timeout` too long to be represented, such as
+    /// `Duration::MAX`, waits without a deadline.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use throttle::{Clock, MockClock, Throttle};
+    ///
+    /// let clock = MockClock::new();
+    /// let start = clock.now();
+    /// let mut throttle = Throttle::with_clock(Duration::from_secs(10), 1, clock.clone());
+    ///
+    /// throttle.acquire_blocking();
+    /// assert_eq!(throttle.acquire_timeout(Duration::from_secs(5)), Err(start + Duration::from_secs(10)));
+    /// assert_eq!(clock.now(), start, "no time is spent when the deadline would pass");
+    ///
+    /// throttle.acquire_timeout(Duration::from_secs(10)).expect("The first accept expires in time");
+    /// assert_eq!(clock.now(), start + Duration::from_secs(10));
+    ///
+    /// throttle.acquire_timeout(Duration::MAX).expect("There is no deadline");
+    /// assert_eq!(clock.now(), start + Duration::from_secs(20));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn acquire_timeout(&mut self, timeout: Duration) -> Result<(), Instant> {
+        let deadline = self.clock.now().checked_add(timeout);
+        loop {
+            match self.accept() {
+                Ok(()) => return Ok(()),
+                Err(at) if deadline.is_some_and(|deadline| at > deadline) => return Err(at),
+                Err(at) => self.clock.sleep_until(at),
+            }
+        }
+    }
+}
+
+/// The error returned when an operation with a cost is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptNError {
+    /// The limiter does not have enough space now, but will have at the given Instant.
+    Full(Instant),
+    /// The cost is greater than the limit, so the operation can never be accepted.
+    TooLarge,
+}
+
+impl fmt::Display for AcceptNError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcceptNError::Full(_) => write!(f, "the limiter is full"),
+            AcceptNError::TooLarge => write!(f, "the cost exceeds the limit"),
+        }
+    }
+}
+
+impl std::error::Error for AcceptNError {}
+
+/// Converts nanoseconds to a Duration, saturating at `Duration::MAX`.
+pub(crate) fn nanos_to_duration(nanos: u128) -> Duration {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    match u64::try_from(nanos / NANOS_PER_SEC) {
+        Ok(secs) => Duration::new(secs, (nanos % NANOS_PER_SEC) as u32),
+        Err(_) => Duration::MAX,
+    }
 }
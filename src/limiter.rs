@@ -0,0 +1,68 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    AcceptNError, BucketedThrottle, Clock, FixedWindow, Gcra, SlidingWindow, Throttle, TokenBucket,
+};
+
+/// A rate limiter that can check an operation before recording it.
+///
+/// This allows several limiters to be combined, such that an operation is only recorded if all
+/// of them would accept it.
+pub trait Limiter {
+    /// Checks whether an operation that costs `cost` units would be accepted now, without
+    /// recording it.
+    fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError>;
+
+    /// Attempts to accept an operation that costs `cost` units.
+    ///
+    /// If `check_n` has just returned Ok for the same cost, this must not fail.
+    fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError>;
+}
+
+macro_rules! impl_limiter {
+    ($($ty:ident),*) => {
+        $(
+            impl<C: Clock> Limiter for $ty<C> {
+                fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+                    $ty::check_n(self, cost)
+                }
+
+                fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+                    $ty::accept_n(self, cost)
+                }
+            }
+        )*
+    };
+}
+
+impl_limiter!(
+    Throttle,
+    TokenBucket,
+    Gcra,
+    FixedWindow,
+    SlidingWindow,
+    BucketedThrottle
+);
+
+impl<L: Limiter + ?Sized> Limiter for Box<L> {
+    fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        (**self).check_n(cost)
+    }
+
+    fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        (**self).accept_n(cost)
+    }
+}
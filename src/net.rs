@@ -0,0 +1,45 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Connection handling shared by the servers.
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// A callback for errors accepting connections
+pub(crate) type AcceptErrorHandler = Box<dyn Fn(&io::Error) + Send + Sync>;
+
+/// Passes each connection from `incoming` to `handle`.
+///
+/// Errors accepting a connection are often temporary, e.g. when the process has run out of file
+/// descriptors, so they are reported to `on_error` and accepting continues after a short pause.
+pub(crate) fn accept_loop<S>(
+    incoming: impl Iterator<Item = io::Result<S>>,
+    on_error: Option<&AcceptErrorHandler>,
+    mut handle: impl FnMut(S),
+) {
+    for stream in incoming {
+        match stream {
+            Ok(stream) => handle(stream),
+            Err(err) => {
+                if let Some(on_error) = on_error {
+                    on_error(&err);
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+}
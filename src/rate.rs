@@ -0,0 +1,348 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{nanos_to_duration, Gcra, Throttle, TokenBucket};
+
+/// The units accepted in a period, from the longest to the shortest.
+///
+/// The first name of each unit is used for [`Display`](fmt::Display).
+const UNITS: &[(&[&str], u128)] = &[
+    (&["d", "day", "days"], 86_400_000_000_000),
+    (&["h", "hr", "hour", "hours"], 3_600_000_000_000),
+    (&["min", "m", "minute", "minutes"], 60_000_000_000),
+    (&["s", "sec", "second", "seconds"], 1_000_000_000),
+    (&["ms", "millisecond", "milliseconds"], 1_000_000),
+    (&["us", "microsecond", "microseconds"], 1_000),
+    (&["ns", "nanosecond", "nanoseconds"], 1),
+];
+
+/// Rate describes a limit of `count` operations per `period`, with an optional burst.
+///
+/// Rates can be parsed from strings in the following forms, case-insensitively:
+///
+/// - `100/min`, `100/5min`: `count` operations per period with an optional multiplier
+/// - `5 per second`, `5 per 10 seconds`
+/// - `10r/s`, as in nginx
+///
+/// Each form may be followed by `burst=<n>` to allow up to `n` operations at once. The units are
+/// `d`, `h`, `min` (or `m`), `s`, `ms`, `us` and `ns`, as well as their names in English.
+///
+/// Formatting a Rate produces a string that parses to the same Rate.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{ParseRateErrorKind, Rate};
+///
+/// let rate: Rate = "100/min".parse()?;
+/// assert_eq!(rate, Rate::new(100, Duration::from_secs(60)));
+/// assert_eq!(rate.timeout(), Duration::from_secs(60));
+/// assert_eq!(rate.threshold(), 100);
+///
+/// let rate: Rate = "5 per 10 seconds".parse()?;
+/// assert_eq!(rate.to_string(), "5/10s");
+///
+/// // 10 operations per second in bursts of up to 20
+/// let rate: Rate = "10r/s burst=20".parse()?;
+/// assert_eq!(rate.burst(), Some(20));
+/// assert_eq!(rate.timeout(), Duration::from_secs(2));
+/// assert_eq!(rate.to_string(), "10/s burst=20");
+/// assert_eq!(rate.to_string().parse::<Rate>()?, rate);
+///
+/// // 1000 operations per nanosecond cannot be limited in bursts of one
+/// let err = "1000/ns burst=1".parse::<Rate>().unwrap_err();
+/// assert_eq!(err.kind(), &ParseRateErrorKind::BurstTooShort);
+///
+/// let err = "100/fortnight".parse::<Rate>().unwrap_err();
+/// assert_eq!(err.to_string(), "unknown time unit \"fortnight\" in rate \"100/fortnight\"");
+/// # Ok::<(), throttle::ParseRateError>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rate {
+    count: usize,
+    period: Duration,
+    burst: Option<usize>,
+}
+
+impl Rate {
+    /// Creates a new Rate of `count` operations per `period`
+    ///
+    /// # Panics
+    /// Panics if `count` or `period` is zero.
+    pub fn new(count: usize, period: Duration) -> Rate {
+        assert!(count > 0, "count must be positive");
+        assert!(period > Duration::from_secs(0), "period must be positive");
+        Rate {
+            count,
+            period,
+            burst: None,
+        }
+    }
+
+    /// Returns this Rate with a burst of up to `burst` operations at once
+    ///
+    /// # Panics
+    /// Panics if `burst` is zero, or if `burst` operations take less than a nanosecond at this
+    /// rate.
+    pub fn with_burst(self, burst: usize) -> Rate {
+        assert!(burst > 0, "burst must be positive");
+        let rate = Rate {
+            burst: Some(burst),
+            ..self
+        };
+        assert!(
+            rate.timeout_nanos() != Some(0),
+            "burst must take at least a nanosecond"
+        );
+        rate
+    }
+
+    /// Returns the number of operations per period
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the period
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns the burst, if any
+    pub fn burst(&self) -> Option<usize> {
+        self.burst
+    }
+
+    /// Returns the number of operations allowed within [`timeout`](Rate::timeout), which is the
+    /// burst if any, or the count otherwise
+    pub fn threshold(&self) -> usize {
+        self.burst.unwrap_or(self.count)
+    }
+
+    /// Returns the duration in which [`threshold`](Rate::threshold) operations are emitted at
+    /// this rate, saturating at `Duration::MAX`
+    pub fn timeout(&self) -> Duration {
+        match self.timeout_nanos() {
+            Some(nanos) => nanos_to_duration(nanos),
+            None => Duration::MAX,
+        }
+    }
+
+    /// Returns the timeout in nanoseconds, or None if it overflows
+    fn timeout_nanos(&self) -> Option<u128> {
+        let burst = self.burst.unwrap_or(self.count) as u128;
+        let nanos = self.period.as_nanos().checked_mul(burst)?;
+        Some(nanos / self.count as u128)
+    }
+
+    /// Creates a new Throttle with this rate
+    pub fn throttle(&self) -> Throttle {
+        Throttle::new(self.timeout(), self.threshold())
+    }
+
+    /// Creates a new Gcra with this rate
+    ///
+    /// # Panics
+    /// Panics if the threshold exceeds the number of nanoseconds in the timeout, or if the
+    /// timeout is too long. See [`Gcra::with_clock`].
+    pub fn gcra(&self) -> Gcra {
+        Gcra::new(self.timeout(), self.threshold())
+    }
+
+    /// Creates a new full TokenBucket with this rate
+    pub fn token_bucket(&self) -> TokenBucket {
+        TokenBucket::new(self.threshold(), self.count, self.period)
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.period.as_nanos();
+        let &(names, unit) = UNITS
+            .iter()
+            .find(|&&(_, unit)| nanos.is_multiple_of(unit))
+            .expect("every period is a multiple of 1ns");
+        write!(f, "{}/", self.count)?;
+        if nanos != unit {
+            write!(f, "{}", nanos / unit)?;
+        }
+        write!(f, "{}", names[0])?;
+        if let Some(burst) = self.burst {
+            write!(f, " burst={}", burst)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Rate {
+    type Err = ParseRateError;
+
+    fn from_str(input: &str) -> Result<Rate, ParseRateError> {
+        let err = |kind| ParseRateError {
+            input: input.to_string(),
+            kind,
+        };
+
+        let normalized = input
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_ascii_lowercase();
+        let (count, rest) = normalized
+            .split_once('/')
+            .or_else(|| normalized.split_once(" per "))
+            .ok_or_else(|| err(ParseRateErrorKind::MissingPeriod))?;
+
+        let count = count.trim();
+        let count = count.strip_suffix('r').unwrap_or(count).trim_end();
+        let count = count
+            .parse::<usize>()
+            .map_err(|_| err(ParseRateErrorKind::InvalidCount(count.to_string())))?;
+        if count == 0 {
+            return Err(err(ParseRateErrorKind::ZeroCount));
+        }
+
+        let (period, burst) = match rest.find("burst") {
+            Some(index) => (&rest[..index], Some(&rest[index + "burst".len()..])),
+            None => (rest, None),
+        };
+
+        let period = period.trim().trim_end_matches(',').trim_end();
+        let digits = period.len()
+            - period
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let multiplier = match &period[..digits] {
+            "" => 1,
+            multiplier => multiplier
+                .parse::<u128>()
+                .map_err(|_| err(ParseRateErrorKind::PeriodTooLong))?,
+        };
+        let unit = period[digits..].trim_start();
+        if unit.is_empty() {
+            return Err(err(ParseRateErrorKind::MissingUnit));
+        }
+        let &(_, unit_nanos) = UNITS
+            .iter()
+            .find(|(names, _)| names.contains(&unit))
+            .ok_or_else(|| err(ParseRateErrorKind::InvalidUnit(unit.to_string())))?;
+        let nanos = multiplier
+            .checked_mul(unit_nanos)
+            .filter(|&nanos| nanos / 1_000_000_000 <= u128::from(u64::MAX))
+            .ok_or_else(|| err(ParseRateErrorKind::PeriodTooLong))?;
+        if nanos == 0 {
+            return Err(err(ParseRateErrorKind::ZeroPeriod));
+        }
+
+        let mut rate = Rate::new(count, nanos_to_duration(nanos));
+        if let Some(burst) = burst {
+            let burst = burst.trim_start();
+            let burst = burst.strip_prefix('=').unwrap_or(burst).trim();
+            let burst = burst
+                .parse::<usize>()
+                .map_err(|_| err(ParseRateErrorKind::InvalidBurst(burst.to_string())))?;
+            if burst == 0 {
+                return Err(err(ParseRateErrorKind::InvalidBurst(burst.to_string())));
+            }
+            let burst_rate = Rate {
+                burst: Some(burst),
+                ..rate
+            };
+            match burst_rate.timeout_nanos() {
+                Some(0) => return Err(err(ParseRateErrorKind::BurstTooShort)),
+                Some(nanos) if nanos / 1_000_000_000 <= u128::from(u64::MAX) => {}
+                _ => return Err(err(ParseRateErrorKind::BurstTooLong)),
+            }
+            rate = rate.with_burst(burst);
+        }
+        Ok(rate)
+    }
+}
+
+impl TryFrom<&str> for Rate {
+    type Error = ParseRateError;
+
+    fn try_from(input: &str) -> Result<Rate, ParseRateError> {
+        input.parse()
+    }
+}
+
+/// The error returned when a [`Rate`] cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRateError {
+    input: String,
+    kind: ParseRateErrorKind,
+}
+
+impl ParseRateError {
+    /// Returns the reason that the rate is invalid
+    pub fn kind(&self) -> &ParseRateErrorKind {
+        &self.kind
+    }
+}
+
+/// The reason that a [`Rate`] cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseRateErrorKind {
+    /// The rate does not contain `/` or `per`.
+    MissingPeriod,
+    /// The count is not a non-negative integer.
+    InvalidCount(String),
+    /// The count is zero.
+    ZeroCount,
+    /// The period does not have a unit.
+    MissingUnit,
+    /// The unit of the period is not recognized.
+    InvalidUnit(String),
+    /// The period is zero.
+    ZeroPeriod,
+    /// The period does not fit in a Duration.
+    PeriodTooLong,
+    /// The burst is not a positive integer.
+    InvalidBurst(String),
+    /// The burst takes less than a nanosecond at this rate.
+    BurstTooShort,
+    /// The burst takes too long at this rate to fit in a Duration.
+    BurstTooLong,
+}
+
+impl fmt::Display for ParseRateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseRateErrorKind::MissingPeriod => write!(
+                f,
+                "expected \"<count>/<period>\" or \"<count> per <period>\""
+            )?,
+            ParseRateErrorKind::InvalidCount(count) => write!(f, "invalid count {:?}", count)?,
+            ParseRateErrorKind::ZeroCount => write!(f, "count must be positive")?,
+            ParseRateErrorKind::MissingUnit => write!(f, "missing time unit")?,
+            ParseRateErrorKind::InvalidUnit(unit) => write!(f, "unknown time unit {:?}", unit)?,
+            ParseRateErrorKind::ZeroPeriod => write!(f, "period must be positive")?,
+            ParseRateErrorKind::PeriodTooLong => write!(f, "period is too long")?,
+            ParseRateErrorKind::InvalidBurst(burst) => write!(f, "invalid burst {:?}", burst)?,
+            ParseRateErrorKind::BurstTooShort => {
+                write!(f, "burst takes less than a nanosecond at this rate")?
+            }
+            ParseRateErrorKind::BurstTooLong => write!(f, "burst takes too long at this rate")?,
+        }
+        write!(f, " in rate {:?}", self.input)
+    }
+}
+
+impl std::error::Error for ParseRateError {}
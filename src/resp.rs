@@ -0,0 +1,439 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A server for a subset of the Redis protocol (RESP), enabled by the `resp` feature.
+//!
+//! This allows existing Redis clients to use the limiters of this crate. The `throttle-resp`
+//! binary runs a server from the command line. The following commands are supported:
+//!
+//! - `CL.THROTTLE <key> <max_burst> <count per period> <period> [<quantity>]` accepts `quantity`
+//!   (default 1) operations for `key` with a [`Gcra`] limiting to `count` operations per `period`
+//!   seconds with bursts of up to `max_burst + 1`. The reply is an array of five integers: 1 if
+//!   the operation was limited or 0 otherwise, the total limit, the remaining limit, the seconds
+//!   until the operation can be retried (-1 if it was accepted), and the seconds until the
+//!   limit resets. If `key` was throttled with other parameters before, its accepted operations
+//!   are kept and the new parameters apply from now on.
+//! - `GET <key>` replies with the total limit, the remaining limit and the seconds until the
+//!   limit resets of `key`, or a null reply if `key` has no state.
+//! - `RESET <key>` removes the state of `key`, replying with 1 if it existed or 0 otherwise.
+//! - `PING [<message>]` replies with `PONG` or the message.
+//! - `QUIT` closes the connection.
+//!
+//! Commands may be sent as RESP arrays of bulk strings, or as inline commands separated by
+//! spaces.
+//!
+//! Arguments out of range are answered with an error. Malformed input, bulk strings over 512 MB
+//! and lines over 64 KB are answered with a protocol error followed by closing the connection.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Instant;
+
+use crate::net::{accept_loop, AcceptErrorHandler};
+use crate::{nanos_to_duration, Gcra};
+
+/// The maximum length of a bulk string, as in Redis
+const MAX_BULK: usize = 512 * 1024 * 1024;
+/// The maximum number of arguments in a command
+const MAX_ARGS: usize = 1024 * 1024;
+/// The maximum length of an inline command or a header line, including the line ending
+const MAX_LINE: u64 = 64 * 1024;
+
+/// The parameters of a `CL.THROTTLE` limiter
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Params {
+    max_burst: u64,
+    count: u64,
+    period: u64,
+}
+
+struct Entry {
+    params: Params,
+    /// The maximum burst plus one, which fits in both `usize` and `i64`
+    limit: i64,
+    gcra: Gcra,
+}
+
+impl Entry {
+    /// Creates an Entry, or returns an error message if the parameters are out of range
+    fn new(params: Params) -> Result<Entry, &'static str> {
+        if params.count == 0 || params.period == 0 {
+            return Err("count and period must be positive");
+        }
+        let limit = params
+            .max_burst
+            .checked_add(1)
+            .and_then(|limit| i64::try_from(limit).ok())
+            .filter(|&limit| usize::try_from(limit).is_ok())
+            .ok_or("max_burst is too large")?;
+
+        let interval = u128::from(params.period) * 1_000_000_000 / u128::from(params.count);
+        if interval == 0 {
+            return Err("count must not exceed the period in nanoseconds");
+        }
+        let timeout = interval
+            .checked_mul(limit as u128)
+            .filter(|&nanos| nanos / 1_000_000_000 <= u128::from(u64::MAX))
+            .map(nanos_to_duration)
+            .ok_or("period is too long")?;
+        // the same condition under which `Gcra::new` panics
+        Instant::now()
+            .checked_add(timeout)
+            .and_then(|tat| tat.checked_add(timeout))
+            .ok_or("period is too long")?;
+        Ok(Entry {
+            params,
+            limit,
+            gcra: Gcra::new(timeout, limit as usize),
+        })
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    fn remaining(&mut self) -> i64 {
+        self.limit() - self.gcra.size() as i64
+    }
+
+    fn reset_after(&self) -> i64 {
+        seconds_until(self.gcra.reset_at())
+    }
+}
+
+fn seconds_until(at: Instant) -> i64 {
+    let wait = at.saturating_duration_since(Instant::now());
+    wait.as_nanos().div_ceil(1_000_000_000) as i64
+}
+
+/// A RESP reply
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Reply::Simple(message) => write!(writer, "+{}\r\n", message),
+            Reply::Error(message) => write!(writer, "-ERR {}\r\n", message),
+            Reply::Integer(value) => write!(writer, ":{}\r\n", value),
+            Reply::Bulk(data) => {
+                write!(writer, "${}\r\n", data.len())?;
+                writer.write_all(data)?;
+                writer.write_all(b"\r\n")
+            }
+            Reply::Null => write!(writer, "$-1\r\n"),
+            Reply::Array(items) => {
+                write!(writer, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write(writer))
+            }
+        }
+    }
+}
+
+/// RespServer serves `CL.THROTTLE` and related commands over TCP.
+///
+/// ```
+/// use std::io::{BufRead, BufReader, Write};
+/// use std::net::{TcpListener, TcpStream};
+/// use throttle::resp::RespServer;
+///
+/// let listener = TcpListener::bind("127.0.0.1:0")?;
+/// let addr = listener.local_addr()?;
+/// RespServer::new().spawn(listener);
+///
+/// let mut stream = TcpStream::connect(addr)?;
+/// let mut reader = BufReader::new(stream.try_clone()?);
+/// let mut read_reply = |lines: usize| -> std::io::Result<String> {
+///     let mut reply = String::new();
+///     for _ in 0..lines {
+///         reader.read_line(&mut reply)?;
+///     }
+///     Ok(reply)
+/// };
+///
+/// stream.write_all(b"*5\r\n$11\r\nCL.THROTTLE\r\n$4\r\nuser\r\n$1\r\n1\r\n$2\r\n10\r\n$2\r\n60\r\n")?;
+/// assert_eq!(read_reply(6)?, "*5\r\n:0\r\n:2\r\n:1\r\n:-1\r\n:6\r\n");
+///
+/// stream.write_all(b"CL.THROTTLE user 1 10 60 2\r\n")?;
+/// assert_eq!(read_reply(6)?, "*5\r\n:1\r\n:2\r\n:1\r\n:6\r\n:6\r\n");
+///
+/// // changing the parameters keeps the accepted operations
+/// stream.write_all(b"CL.THROTTLE user 1 10 61 2\r\n")?;
+/// assert_eq!(read_reply(6)?, "*5\r\n:1\r\n:2\r\n:1\r\n:6\r\n:6\r\n");
+///
+/// stream.write_all(b"RESET user\r\n")?;
+/// assert_eq!(read_reply(1)?, ":1\r\n");
+/// stream.write_all(b"PING\r\n")?;
+/// assert_eq!(read_reply(1)?, "+PONG\r\n");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct RespServer {
+    entries: Mutex<Entries>,
+    on_accept_error: Option<AcceptErrorHandler>,
+}
+
+struct Entries {
+    map: HashMap<Vec<u8>, Entry>,
+    /// The map length at which entries that have fully reset are removed
+    sweep_at: usize,
+}
+
+impl Default for RespServer {
+    fn default() -> RespServer {
+        RespServer {
+            entries: Mutex::new(Entries {
+                map: HashMap::new(),
+                sweep_at: 16,
+            }),
+            on_accept_error: None,
+        }
+    }
+}
+
+impl RespServer {
+    /// Creates a new RespServer without any state
+    pub fn new() -> RespServer {
+        RespServer::default()
+    }
+
+    /// Calls `handler` with errors accepting connections, e.g. to log them.
+    ///
+    /// These errors are often temporary, so the server keeps accepting connections after them.
+    pub fn on_accept_error(&mut self, handler: impl Fn(&io::Error) + Send + Sync + 'static) {
+        self.on_accept_error = Some(Box::new(handler));
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        // a panic while holding the lock must not affect other connections
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Serves connections from `listener` on the current thread, one thread per connection
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        accept_loop(
+            listener.incoming(),
+            self.on_accept_error.as_ref(),
+            |stream| {
+                let server = Arc::clone(&self);
+                thread::spawn(move || {
+                    // commands wait for each reply, so delaying small writes only adds latency
+                    stream.set_nodelay(true)?;
+                    let reader = BufReader::new(stream.try_clone()?);
+                    server.handle(reader, BufWriter::new(stream))
+                });
+            },
+        );
+        Ok(())
+    }
+
+    /// Serves connections from `listener` on a background thread
+    pub fn spawn(self, listener: TcpListener) -> thread::JoinHandle<io::Result<()>> {
+        let server = Arc::new(self);
+        thread::spawn(move || server.serve(listener))
+    }
+
+    /// Serves commands on a single connection until it is closed.
+    ///
+    /// `writer` is flushed after each reply, so it should be buffered.
+    pub fn handle(&self, mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        loop {
+            let command = match read_command(&mut reader) {
+                Ok(Some(command)) => command,
+                Ok(None) => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    // the stream cannot be resynchronized after a protocol error
+                    Reply::Error(format!("Protocol error: {}", err)).write(&mut writer)?;
+                    return writer.flush();
+                }
+                Err(err) => return Err(err),
+            };
+            if command.is_empty() {
+                continue;
+            }
+
+            let quit = command[0].eq_ignore_ascii_case(b"QUIT");
+            let reply = if quit {
+                Reply::Simple("OK")
+            } else {
+                self.execute(&command)
+            };
+            reply.write(&mut writer)?;
+            writer.flush()?;
+            if quit {
+                return Ok(());
+            }
+        }
+    }
+
+    fn execute(&self, command: &[Vec<u8>]) -> Reply {
+        let name = String::from_utf8_lossy(&command[0]).to_ascii_uppercase();
+        let args = &command[1..];
+        match (name.as_str(), args) {
+            ("PING", []) => Reply::Simple("PONG"),
+            ("PING", [message]) => Reply::Bulk(message.clone()),
+            ("CL.THROTTLE", [key, rest @ ..]) if rest.len() == 3 || rest.len() == 4 => {
+                let numbers: Option<Vec<u64>> = rest.iter().map(|arg| parse_u64(arg)).collect();
+                match numbers.as_deref() {
+                    Some(&[max_burst, count, period, ref quantity @ ..]) => {
+                        let params = Params {
+                            max_burst,
+                            count,
+                            period,
+                        };
+                        self.throttle(key, params, quantity.first().copied().unwrap_or(1))
+                    }
+                    _ => Reply::Error("invalid CL.THROTTLE arguments".to_string()),
+                }
+            }
+            ("GET", [key]) => {
+                let mut entries = self.entries();
+                match entries.map.get_mut(key) {
+                    Some(entry) => Reply::Array(vec![
+                        Reply::Integer(entry.limit()),
+                        Reply::Integer(entry.remaining()),
+                        Reply::Integer(entry.reset_after()),
+                    ]),
+                    None => Reply::Null,
+                }
+            }
+            ("RESET", [key]) => {
+                let removed = self.entries().map.remove(key).is_some();
+                Reply::Integer(removed as i64)
+            }
+            _ => Reply::Error(format!(
+                "unknown command or wrong number of arguments for '{}'",
+                name
+            )),
+        }
+    }
+
+    fn throttle(&self, key: &[u8], params: Params, quantity: u64) -> Reply {
+        // validate before locking, so that invalid arguments cannot affect other connections
+        let fresh = match Entry::new(params) {
+            Ok(entry) => entry,
+            Err(message) => {
+                return Reply::Error(format!("invalid CL.THROTTLE arguments: {}", message))
+            }
+        };
+        let quantity = usize::try_from(quantity).unwrap_or(usize::MAX);
+
+        let mut entries = self.entries();
+        let Entries { map, sweep_at } = &mut *entries;
+        if map.len() >= *sweep_at {
+            let now = Instant::now();
+            map.retain(|_, entry| entry.gcra.reset_at() > now);
+            *sweep_at = (map.len() * 2).max(16);
+        }
+        let entry = match map.get_mut(key) {
+            Some(entry) if entry.params == params => entry,
+            Some(entry) => {
+                // changing the parameters must not reset the limit
+                let mut fresh = fresh;
+                fresh.gcra.resume(&entry.gcra);
+                *entry = fresh;
+                entry
+            }
+            None => map.entry(key.to_vec()).or_insert(fresh),
+        };
+
+        let (limited, retry_after) = match entry.gcra.accept_n(quantity) {
+            Ok(()) => (0, -1),
+            Err(crate::AcceptNError::Full(at)) => (1, seconds_until(at)),
+            Err(crate::AcceptNError::TooLarge) => (1, -1),
+        };
+        Reply::Array(vec![
+            Reply::Integer(limited),
+            Reply::Integer(entry.limit()),
+            Reply::Integer(entry.remaining()),
+            Reply::Integer(retry_after),
+            Reply::Integer(entry.reset_after()),
+        ])
+    }
+}
+
+fn parse_u64(arg: &[u8]) -> Option<u64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads a line of up to [`MAX_LINE`] bytes into `line`, returning the number of bytes read.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let len = reader.take(MAX_LINE).read_line(line)?;
+    if !line.ends_with('\n') && len as u64 == MAX_LINE {
+        return Err(invalid("line is too long"));
+    }
+    Ok(len)
+}
+
+/// Reads a RESP array of bulk strings or an inline command, or returns None at the end of input.
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let mut line = String::new();
+    if read_line(reader, &mut line)? == 0 {
+        return Ok(None);
+    }
+    let line = line.trim_end();
+
+    let count = match line.strip_prefix('*') {
+        Some(count) => count
+            .parse::<usize>()
+            .ok()
+            .filter(|&count| count <= MAX_ARGS)
+            .ok_or_else(|| invalid("invalid array length"))?,
+        None => {
+            let words = line.split_whitespace().map(|word| word.as_bytes().to_vec());
+            return Ok(Some(words.collect()));
+        }
+    };
+
+    let mut command = Vec::with_capacity(count.min(16));
+    for _ in 0..count {
+        let mut header = String::new();
+        read_line(reader, &mut header)?;
+        let len = header
+            .trim_end()
+            .strip_prefix('$')
+            .and_then(|len| len.parse::<usize>().ok())
+            .ok_or_else(|| invalid("expected a bulk string"))?;
+        if len > MAX_BULK {
+            return Err(invalid("invalid bulk length"));
+        }
+
+        // read incrementally, so that memory is only used for data that has been received
+        let mut data = Vec::new();
+        reader.take(len as u64 + 2).read_to_end(&mut data)?;
+        if data.len() < len + 2 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if !data.ends_with(b"\r\n") {
+            return Err(invalid("bulk string is not terminated"));
+        }
+        data.truncate(len);
+        command.push(data);
+    }
+    Ok(Some(command))
+}
@@ -0,0 +1,336 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A rate-limit server and client, enabled by the `server` feature.
+//!
+//! The server hosts named limits, each of which keeps a separate [`Throttle`](crate::Throttle)
+//! for every key, so that processes that cannot link this crate can share the same limits.
+//! The `throttled` binary runs a server from the command line.
+//!
+//! # Protocol
+//! The protocol is line-based UTF-8 text over a Unix domain socket or TCP. Every line ends with
+//! `\n`, and fields are separated by a single space, so names and keys must not contain
+//! whitespace.
+//!
+//! The client starts by sending its protocol version, and the server replies with the same line
+//! if it supports that version, or an error followed by closing the connection:
+//!
+//! ```text
+//! > THROTTLE 1
+//! < THROTTLE 1
+//! ```
+//!
+//! The client may then send any number of requests, each answered by one response:
+//!
+//! | Request                        | Response                                             |
+//! | ------------------------------ | ---------------------------------------------------- |
+//! | `ACCEPT <limit> <key> <cost>`  | `OK`, `FULL <nanoseconds to wait>` or `TOOLARGE`     |
+//! | `SIZE <limit> <key>`           | `SIZE <remaining items>`                             |
+//! | `PING`                         | `PONG`                                               |
+//!
+//! `ACCEPT` makes the same decision as [`Throttle::accept_n`](crate::Throttle::accept_n). Since
+//! an `Instant` cannot be sent between processes, `FULL` carries the time to wait from when the
+//! response was sent. Any malformed request is answered with `ERR <message>`. Lines longer than
+//! 4096 bytes are answered with an error followed by closing the connection.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+
+use crate::net::{accept_loop, AcceptErrorHandler};
+use crate::{nanos_to_duration, AcceptNError, ShardedThrottle};
+
+/// The protocol version implemented by this module
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const SHARDS: usize = 16;
+
+/// The maximum length of a request line, including the newline
+const MAX_LINE: u64 = 4096;
+
+/// Server hosts named keyed limits.
+///
+/// ```
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use throttle::server::{Client, Server};
+///
+/// let mut server = Server::new();
+/// server.add_limit("api", Duration::from_secs(60), 2);
+/// let listener = TcpListener::bind("127.0.0.1:0")?;
+/// let addr = listener.local_addr()?;
+/// server.spawn_tcp(listener);
+///
+/// let mut client = Client::connect_tcp(addr)?;
+/// client.accept("api", "alice", 1)?.expect("alice has not been throttled");
+/// client.accept("api", "alice", 1)?.expect("alice has one more space");
+/// client.accept("api", "alice", 1)?.expect_err("alice should be throttled");
+/// assert_eq!(client.size("api", "alice")?, 2);
+/// assert_eq!(client.size("api", "bob")?, 0);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Default)]
+pub struct Server {
+    limits: HashMap<String, ShardedThrottle<String>>,
+    on_accept_error: Option<AcceptErrorHandler>,
+}
+
+impl Server {
+    /// Creates a new Server without any limits
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// Adds a limit of `threshold` operations per `timeout` for each key, replacing any limit
+    /// with the same name
+    pub fn add_limit(&mut self, name: &str, timeout: Duration, threshold: usize) {
+        let throttle = ShardedThrottle::new(timeout, threshold, SHARDS);
+        self.limits.insert(name.to_string(), throttle);
+    }
+
+    /// Calls `handler` with errors accepting connections, e.g. to log them.
+    ///
+    /// These errors are often temporary, so the server keeps accepting connections after them.
+    pub fn on_accept_error(&mut self, handler: impl Fn(&io::Error) + Send + Sync + 'static) {
+        self.on_accept_error = Some(Box::new(handler));
+    }
+
+    /// Serves connections from `listener` on the current thread, one thread per connection
+    pub fn serve_tcp(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        accept_loop(
+            listener.incoming(),
+            self.on_accept_error.as_ref(),
+            |stream| {
+                let server = Arc::clone(&self);
+                thread::spawn(move || {
+                    // requests wait for each response, so delaying small writes only adds latency
+                    stream.set_nodelay(true)?;
+                    let reader = BufReader::new(stream.try_clone()?);
+                    server.handle(reader, BufWriter::new(stream))
+                });
+            },
+        );
+        Ok(())
+    }
+
+    /// Serves connections from `listener` on a background thread
+    pub fn spawn_tcp(self, listener: TcpListener) -> thread::JoinHandle<io::Result<()>> {
+        let server = Arc::new(self);
+        thread::spawn(move || server.serve_tcp(listener))
+    }
+
+    /// Serves connections from `listener` on the current thread, one thread per connection
+    #[cfg(unix)]
+    pub fn serve_unix(self: Arc<Self>, listener: UnixListener) -> io::Result<()> {
+        accept_loop(
+            listener.incoming(),
+            self.on_accept_error.as_ref(),
+            |stream| {
+                let server = Arc::clone(&self);
+                thread::spawn(move || {
+                    let reader = BufReader::new(stream.try_clone()?);
+                    server.handle(reader, BufWriter::new(stream))
+                });
+            },
+        );
+        Ok(())
+    }
+
+    /// Serves the protocol on a single connection until it is closed.
+    ///
+    /// `writer` is flushed after each response, so it should be buffered.
+    pub fn handle(&self, mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        let mut line = String::new();
+        if !read_request(&mut reader, &mut writer, &mut line)? {
+            return Ok(());
+        }
+        let version = format!("THROTTLE {}", PROTOCOL_VERSION);
+        if line.trim_end() != version {
+            writeln!(writer, "ERR unsupported version")?;
+            return writer.flush();
+        }
+        writeln!(writer, "{}", version)?;
+        writer.flush()?;
+
+        loop {
+            line.clear();
+            if !read_request(&mut reader, &mut writer, &mut line)? {
+                return Ok(());
+            }
+            let response = self.respond(line.trim_end());
+            writeln!(writer, "{}", response)?;
+            writer.flush()?;
+        }
+    }
+
+    fn respond(&self, request: &str) -> String {
+        let fields: Vec<_> = request.split(' ').collect();
+        match fields[..] {
+            ["ACCEPT", limit, key, cost] => {
+                let throttle = match self.limits.get(limit) {
+                    Some(throttle) => throttle,
+                    None => return format!("ERR unknown limit {}", limit),
+                };
+                let cost = match cost.parse() {
+                    Ok(cost) => cost,
+                    Err(_) => return format!("ERR invalid cost {}", cost),
+                };
+                match throttle.accept_n(key, cost) {
+                    Ok(()) => "OK".to_string(),
+                    Err(AcceptNError::Full(at)) => {
+                        let wait = at.saturating_duration_since(Instant::now());
+                        format!("FULL {}", wait.as_nanos())
+                    }
+                    Err(AcceptNError::TooLarge) => "TOOLARGE".to_string(),
+                }
+            }
+            ["SIZE", limit, key] => match self.limits.get(limit) {
+                Some(throttle) => format!("SIZE {}", throttle.size(key)),
+                None => format!("ERR unknown limit {}", limit),
+            },
+            ["PING"] => "PONG".to_string(),
+            _ => "ERR invalid request".to_string(),
+        }
+    }
+}
+
+/// Reads a request line of up to [`MAX_LINE`] bytes into `line`.
+///
+/// Returns false if the connection should be closed, after replying with an error if the line
+/// is too long.
+fn read_request(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    line: &mut String,
+) -> io::Result<bool> {
+    let len = reader.take(MAX_LINE).read_line(line)?;
+    if len == 0 {
+        return Ok(false);
+    }
+    if !line.ends_with('\n') && len as u64 == MAX_LINE {
+        writeln!(writer, "ERR request too long")?;
+        writer.flush()?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Client connects to a [`Server`].
+pub struct Client {
+    reader: Box<dyn BufRead + Send>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl Client {
+    /// Connects to a server over TCP
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> io::Result<Client> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Client::handshake(Box::new(reader), Box::new(BufWriter::new(stream)))
+    }
+
+    /// Connects to a server over a Unix domain socket
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<Path>) -> io::Result<Client> {
+        let stream = UnixStream::connect(path)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Client::handshake(Box::new(reader), Box::new(BufWriter::new(stream)))
+    }
+
+    fn handshake(
+        reader: Box<dyn BufRead + Send>,
+        writer: Box<dyn Write + Send>,
+    ) -> io::Result<Client> {
+        let mut client = Client { reader, writer };
+        let version = format!("THROTTLE {}", PROTOCOL_VERSION);
+        if client.request(&version)? != version {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the server does not support this protocol version",
+            ));
+        }
+        Ok(client)
+    }
+
+    fn request(&mut self, request: &str) -> io::Result<String> {
+        writeln!(self.writer, "{}", request)?;
+        self.writer.flush()?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim_end();
+        match line.strip_prefix("ERR ") {
+            Some(message) => Err(io::Error::other(message.to_string())),
+            None => Ok(line.to_string()),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units for `key` in the limit `name`.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n) for the meaning of the errors.
+    pub fn accept(
+        &mut self,
+        name: &str,
+        key: &str,
+        cost: usize,
+    ) -> io::Result<Result<(), AcceptNError>> {
+        let response = self.request(&format!("ACCEPT {} {} {}", name, key, cost))?;
+        let received = Instant::now();
+        match response.split(' ').collect::<Vec<_>>()[..] {
+            ["OK"] => Ok(Ok(())),
+            ["TOOLARGE"] => Ok(Err(AcceptNError::TooLarge)),
+            ["FULL", wait] => {
+                let wait = wait.parse().map_err(|_| invalid_response(&response))?;
+                Ok(Err(AcceptNError::Full(received + nanos_to_duration(wait))))
+            }
+            _ => Err(invalid_response(&response)),
+        }
+    }
+
+    /// Returns the number of remaining items for `key` in the limit `name`
+    pub fn size(&mut self, name: &str, key: &str) -> io::Result<usize> {
+        let response = self.request(&format!("SIZE {} {}", name, key))?;
+        response
+            .strip_prefix("SIZE ")
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| invalid_response(&response))
+    }
+
+    /// Checks that the server is responsive
+    pub fn ping(&mut self) -> io::Result<()> {
+        let response = self.request("PING")?;
+        if response != "PONG" {
+            return Err(invalid_response(&response));
+        }
+        Ok(())
+    }
+}
+
+fn invalid_response(response: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid response {:?}", response),
+    )
+}
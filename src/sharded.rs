@@ -0,0 +1,151 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::{AcceptNError, Clock, KeyedThrottle, SystemClock};
+
+/// ShardedThrottle is a [`KeyedThrottle`] that can be shared between threads.
+///
+/// Keys are distributed over a number of shards by their hash, and each shard has its own lock,
+/// so threads accepting different keys rarely contend with each other.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use throttle::ShardedThrottle;
+///
+/// let throttle = Arc::new(ShardedThrottle::new(Duration::from_secs(60), 3, 16));
+/// let threads: Vec<_> = (0..4)
+///     .map(|i| {
+///         let throttle = Arc::clone(&throttle);
+///         std::thread::spawn(move || {
+///             (0..5).filter(|_| throttle.accept(&format!("user{}", i % 2)).is_ok()).count()
+///         })
+///     })
+///     .collect();
+/// let accepted: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+/// assert_eq!(accepted, 6);
+/// assert_eq!(throttle.size("user0"), 3);
+/// ```
+pub struct ShardedThrottle<K, C = SystemClock> {
+    shards: Box<[Mutex<KeyedThrottle<K, C>>]>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq> ShardedThrottle<K> {
+    /// Creates a new ShardedThrottle with `shards` shards
+    ///
+    /// # Panics
+    /// Panics if `shards` is zero.
+    pub fn new(timeout: Duration, threshold: usize, shards: usize) -> ShardedThrottle<K> {
+        ShardedThrottle::with_clock(timeout, threshold, shards, SystemClock)
+    }
+}
+
+impl<K: Hash + Eq, C: Clock + Clone> ShardedThrottle<K, C> {
+    /// Creates a new ShardedThrottle with `shards` shards that reads the time from `clock`
+    ///
+    /// # Panics
+    /// Panics if `shards` is zero.
+    pub fn with_clock(
+        timeout: Duration,
+        threshold: usize,
+        shards: usize,
+        clock: C,
+    ) -> ShardedThrottle<K, C> {
+        assert!(shards > 0, "shards must be positive");
+        ShardedThrottle {
+            shards: (0..shards)
+                .map(|_| Mutex::new(KeyedThrottle::with_clock(timeout, threshold, clock.clone())))
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> MutexGuard<'_, KeyedThrottle<K, C>> {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        self.shards[index].lock().unwrap()
+    }
+
+    /// Returns the number of tracked keys over all shards
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    /// Returns true if no keys are tracked
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the throttles of all keys that have become empty
+    pub fn flush(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().flush();
+        }
+    }
+
+    /// Returns the number of remaining items in the throttle of `key`
+    pub fn size<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).size(key)
+    }
+
+    /// Checks that the throttle of `key` is available to accept.
+    ///
+    /// The availability may change at any time since other threads may accept concurrently.
+    pub fn available<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).available(key)
+    }
+
+    /// Attempts to accept an operation for `key`.
+    ///
+    /// See [`Throttle::accept`](crate::Throttle::accept).
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn accept<Q>(&self, key: &Q) -> Result<(), Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.shard(key).accept(key)
+    }
+
+    /// Attempts to accept an operation for `key` that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n).
+    pub fn accept_n<Q>(&self, key: &Q, cost: usize) -> Result<(), AcceptNError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.shard(key).accept_n(key, cost)
+    }
+}
@@ -0,0 +1,209 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cross-process limiting, enabled by the `shared` feature.
+
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use memmap2::MmapRaw;
+
+use crate::atomic::accept_at;
+use crate::{AcceptNError, Clock, SystemClock};
+
+const MAGIC: &[u8; 8] = b"THRTSHM1";
+/// Magic, timeout in nanoseconds, threshold and the theoretical arrival time, each 8 bytes
+const FILE_LEN: u64 = 32;
+const TAT_OFFSET: usize = 24;
+
+/// SharedThrottle is a rate limiter shared by all processes on the same host that open the same
+/// file.
+///
+/// It implements the same algorithm as [`AtomicGcra`](crate::AtomicGcra), storing the
+/// theoretical arrival time in a memory-mapped file as nanoseconds since the Unix epoch. Since
+/// the state is shared through wall-clock time, adjustments of the system clock affect the
+/// limit.
+///
+/// Requires the `shared` feature.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::SharedThrottle;
+///
+/// let path = std::env::temp_dir().join(format!("throttle-doc-{}.shm", std::process::id()));
+/// # let _ = std::fs::remove_file(&path);
+/// // each process opens the same file with the same parameters
+/// let first = SharedThrottle::open(&path, Duration::from_secs(60), 2)?;
+/// let second = SharedThrottle::open(&path, Duration::from_secs(60), 2)?;
+///
+/// first.accept().expect("The throttle is empty");
+/// second.accept().expect("The throttle has one more space");
+/// first.accept().expect_err("The throttle is shared and full");
+/// second.accept().expect_err("The throttle is shared and full");
+///
+/// assert!(SharedThrottle::open(&path, Duration::from_secs(60), 3).is_err());
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct SharedThrottle<C = SystemClock> {
+    timeout: u64,
+    threshold: usize,
+    interval: u64,
+    map: MmapRaw,
+    clock: C,
+}
+
+impl SharedThrottle {
+    /// Opens or creates the shared state at `path`
+    ///
+    /// Returns an error if the file was created with a different `timeout` or `threshold`.
+    ///
+    /// # Panics
+    /// Panics if `threshold` is zero or exceeds the number of nanoseconds in `timeout`, or if
+    /// `timeout` does not fit in 64-bit nanoseconds.
+    pub fn open(
+        path: impl AsRef<Path>,
+        timeout: Duration,
+        threshold: usize,
+    ) -> io::Result<SharedThrottle> {
+        SharedThrottle::open_with_clock(path, timeout, threshold, SystemClock)
+    }
+}
+
+impl<C: Clock> SharedThrottle<C> {
+    /// Opens or creates the shared state at `path`, reading the time from `clock`
+    ///
+    /// See [`open`](SharedThrottle::open).
+    pub fn open_with_clock(
+        path: impl AsRef<Path>,
+        timeout: Duration,
+        threshold: usize,
+        clock: C,
+    ) -> io::Result<SharedThrottle<C>> {
+        assert!(threshold > 0, "threshold must be positive");
+        let timeout = u64::try_from(timeout.as_nanos()).expect("timeout is too long");
+        assert!(
+            timeout >= threshold as u64,
+            "timeout must be at least threshold nanoseconds"
+        );
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        init(&mut file, timeout, threshold as u64)?;
+
+        // the file is only accessed through raw pointers into the mapping, see `tat`
+        let map = MmapRaw::map_raw(&file)?;
+        Ok(SharedThrottle {
+            timeout,
+            threshold,
+            interval: timeout / threshold as u64,
+            map,
+            clock,
+        })
+    }
+
+    fn tat(&self) -> &AtomicU64 {
+        // Safety: the mapping is page-aligned and at least FILE_LEN bytes long, so the pointer is
+        // aligned and valid for as long as `self.map` lives. The raw pointer does not go through a
+        // `&[u8]`, so writes by other processes only happen behind the `AtomicU64`.
+        unsafe { &*(self.map.as_mut_ptr().add(TAT_OFFSET) as *const AtomicU64) }
+    }
+
+    fn now(&self) -> u64 {
+        let since_epoch = self.clock.system_now().duration_since(UNIX_EPOCH);
+        since_epoch.unwrap_or_default().as_nanos() as u64
+    }
+
+    /// Returns the number of operations that have not been emitted yet
+    pub fn size(&self) -> usize {
+        let backlog = self
+            .tat()
+            .load(Ordering::Acquire)
+            .saturating_sub(self.now());
+        backlog.div_ceil(self.interval) as usize
+    }
+
+    /// Checks that the throttle is available to accept.
+    ///
+    /// The availability may change at any time since other processes may accept concurrently.
+    pub fn available(&self) -> bool {
+        let now = self.now();
+        let tat = self.tat().load(Ordering::Acquire);
+        tat.max(now) + self.interval - now <= self.timeout
+    }
+
+    /// Attempts to accept an operation.
+    ///
+    /// See [`Gcra::accept`](crate::Gcra::accept).
+    pub fn accept(&self) -> Result<(), Instant> {
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => unreachable!("threshold is positive"),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n) for the meaning of the errors.
+    pub fn accept_n(&self, cost: usize) -> Result<(), AcceptNError> {
+        if cost > self.threshold {
+            return Err(AcceptNError::TooLarge);
+        }
+
+        let now = self.now();
+        let instant = self.clock.now();
+        accept_at(self.tat(), now, self.interval * cost as u64, self.timeout)
+            .map_err(|allow_at| AcceptNError::Full(instant + Duration::from_nanos(allow_at - now)))
+    }
+}
+
+/// Writes the header of a new file, or validates the header of an existing one.
+fn init(file: &mut File, timeout: u64, threshold: u64) -> io::Result<()> {
+    let mut header = [0; FILE_LEN as usize];
+    header[0..8].copy_from_slice(MAGIC);
+    header[8..16].copy_from_slice(&timeout.to_le_bytes());
+    header[16..24].copy_from_slice(&threshold.to_le_bytes());
+
+    file.lock()?;
+    let result = (|| {
+        if file.metadata()?.len() < FILE_LEN {
+            // the file is new, or its creator crashed before writing the header
+            file.set_len(0)?;
+            file.write_all(&header)?;
+            return file.sync_all();
+        }
+
+        let mut existing = [0; 24];
+        file.read_exact(&mut existing)?;
+        if existing[..] != header[..24] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the shared throttle was created with different parameters",
+            ));
+        }
+        Ok(())
+    })();
+    file.unlock()?;
+    result
+}
@@ -0,0 +1,190 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use crate::{nanos_to_duration, AcceptNError, Clock, SystemClock};
+
+/// SlidingWindow is an approximate sliding-window counter with constant memory usage.
+///
+/// Time is divided into consecutive windows of length `timeout`. Only the number of operations in
+/// the current and the previous window are stored. The number of operations in the last `timeout`
+/// is estimated as the current count plus the previous count weighted by the fraction of the
+/// previous window that still overlaps with the last `timeout`.
+///
+/// # Error
+/// The estimate assumes that operations in the previous window were spread evenly. Compared to a
+/// [`Throttle`](crate::Throttle) with the same parameters:
+///
+/// - If the previous operations were concentrated at the end of the previous window, they are
+///   expired too early. In the worst case, up to `2 * threshold` operations are accepted within a
+///   single `timeout`, i.e. the error is at most `threshold`.
+/// - If the previous operations were concentrated at the start of the previous window, they are
+///   expired too late, so operations that a Throttle would accept may be rejected.
+///
+/// When operations arrive at a steady rate, the estimate is exact.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{Clock, MockClock, SlidingWindow};
+///
+/// let clock = MockClock::new();
+/// let mut window = SlidingWindow::with_clock(Duration::from_secs(10), 4, clock.clone());
+///
+/// for _ in 0..4 {
+///     window.accept().expect("The window has space");
+/// }
+/// window.accept().expect_err("The window should be full");
+///
+/// clock.advance(Duration::from_secs(15)); // half of the previous window overlaps
+/// assert_eq!(window.size(), 2);
+/// window.accept().expect("Half of the previous operations have expired");
+/// window.accept().expect("Half of the previous operations have expired");
+/// // the previous window weighs 3/4 after 2.5 more seconds
+/// assert_eq!(window.accept(), Err(clock.now() + Duration::from_millis(2500)));
+/// ```
+pub struct SlidingWindow<C = SystemClock> {
+    timeout: Duration,
+    threshold: usize,
+    origin: Instant,
+    /// The index of the current window since `origin`
+    index: u128,
+    current: usize,
+    previous: usize,
+    clock: C,
+}
+
+impl SlidingWindow {
+    /// Creates a new SlidingWindow
+    ///
+    /// # Panics
+    /// Panics if `timeout` is zero.
+    pub fn new(timeout: Duration, threshold: usize) -> SlidingWindow {
+        SlidingWindow::with_clock(timeout, threshold, SystemClock)
+    }
+}
+
+impl<C: Clock> SlidingWindow<C> {
+    /// Creates a new SlidingWindow that reads the time from `clock`
+    ///
+    /// # Panics
+    /// Panics if `timeout` is zero.
+    pub fn with_clock(timeout: Duration, threshold: usize, clock: C) -> SlidingWindow<C> {
+        assert!(timeout > Duration::from_secs(0), "timeout must be positive");
+        SlidingWindow {
+            timeout,
+            threshold,
+            origin: clock.now(),
+            index: 0,
+            current: 0,
+            previous: 0,
+            clock,
+        }
+    }
+
+    /// Advances to the current window and returns the time elapsed in it, in nanoseconds.
+    fn flush(&mut self) -> u128 {
+        let width = self.timeout.as_nanos();
+        let since = self
+            .clock
+            .now()
+            .saturating_duration_since(self.origin)
+            .as_nanos();
+        let index = since / width;
+        if index == self.index + 1 {
+            self.previous = self.current;
+            self.current = 0;
+        } else if index > self.index + 1 {
+            self.previous = 0;
+            self.current = 0;
+        }
+        self.index = index;
+        since % width
+    }
+
+    /// Returns the start of the window `offset` windows after the current one.
+    fn window_start(&self, offset: u128) -> Instant {
+        self.origin + nanos_to_duration((self.index + offset) * self.timeout.as_nanos())
+    }
+
+    /// Returns the estimated number of operations in the last `timeout`, rounded up
+    pub fn size(&mut self) -> usize {
+        let elapsed = self.flush();
+        let width = self.timeout.as_nanos();
+        let weighted = (self.previous as u128 * (width - elapsed)).div_ceil(width);
+        self.current + weighted as usize
+    }
+
+    /// Checks that the window is available to accept.
+    ///
+    /// The same race conditions as [`Throttle::available`](crate::Throttle::available) apply.
+    pub fn available(&mut self) -> bool {
+        self.size() < self.threshold
+    }
+
+    /// Attempts to accept an operation.
+    ///
+    /// On failure, Err is returned with an Instant indicating the time that the estimate drops
+    /// below the threshold again.
+    ///
+    /// # Panics
+    /// Panics if the threshold is zero.
+    pub fn accept(&mut self) -> Result<(), Instant> {
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("SlidingWindow threshold is zero"),
+        }
+    }
+
+    /// Attempts to accept an operation that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n) for the meaning of the errors.
+    pub fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        self.check_n(cost)?;
+        self.current += cost;
+        Ok(())
+    }
+
+    /// Checks whether an operation that costs `cost` units of the threshold would be accepted
+    /// now, without recording it.
+    ///
+    /// The errors are the same as [`accept_n`](SlidingWindow::accept_n).
+    pub fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        if cost > self.threshold {
+            return Err(AcceptNError::TooLarge);
+        }
+
+        let elapsed = self.flush();
+        let width = self.timeout.as_nanos();
+        let threshold = self.threshold as u128;
+        let previous = self.previous as u128 * (width - elapsed);
+        if previous + (self.current + cost) as u128 * width <= threshold * width {
+            return Ok(());
+        }
+
+        // Find the earliest elapsed time `e` in some window such that
+        // `carried * (width - e) + cost * width <= threshold * width`
+        let (start, carried, spare) = if self.current + cost <= self.threshold {
+            let spare = (self.threshold - self.current - cost) as u128;
+            (self.window_start(0), self.previous as u128, spare)
+        } else {
+            let spare = (self.threshold - cost) as u128;
+            (self.window_start(1), self.current as u128, spare)
+        };
+        let wait = width - (spare * width / carried).min(width);
+        Err(AcceptNError::Full(start + nanos_to_duration(wait)))
+    }
+}
@@ -0,0 +1,116 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, SystemTime};
+
+use crate::{Clock, SystemClock, Throttle};
+
+/// A snapshot of a [`Throttle`] that can be persisted across restarts.
+///
+/// Since an `Instant` is only meaningful within the current process, accepted operations are
+/// stored as wall-clock times. With the `serde` feature, this type implements `Serialize` and
+/// `Deserialize`.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{Clock, MockClock, Throttle};
+///
+/// let clock = MockClock::new();
+/// let mut throttle = Throttle::with_clock(Duration::from_secs(10), 2, clock.clone());
+/// throttle.accept().expect("The throttle is empty");
+/// clock.advance(Duration::from_secs(5));
+/// throttle.accept().expect("The throttle has one more space");
+/// let state = throttle.state();
+///
+/// // the process restarts after 7 seconds
+/// let clock = MockClock::with_system_time(clock.system_now() + Duration::from_secs(7));
+/// let mut throttle = Throttle::restore_with_clock(state, clock.clone());
+/// assert_eq!(throttle.size(), 1, "The first accept expired while offline");
+/// throttle.accept().expect("The throttle has one more space");
+/// throttle.accept().expect_err("The second accept has not expired");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThrottleState {
+    /// The timeout of the throttle
+    pub timeout: Duration,
+    /// The threshold of the throttle
+    pub threshold: usize,
+    /// The wall-clock times and costs of the accepted operations, oldest first
+    pub entries: Vec<(SystemTime, usize)>,
+}
+
+impl Throttle {
+    /// Rebuilds a Throttle from a snapshot.
+    ///
+    /// Time that passed since the snapshot was taken, including time the process was not
+    /// running, counts as elapsed.
+    pub fn restore(state: ThrottleState) -> Throttle {
+        Throttle::restore_with_clock(state, SystemClock)
+    }
+}
+
+impl<C: Clock> Throttle<C> {
+    /// Rebuilds a Throttle that reads the time from `clock` from a snapshot.
+    ///
+    /// See [`restore`](Throttle::restore).
+    pub fn restore_with_clock(mut state: ThrottleState, clock: C) -> Throttle<C> {
+        let now = clock.now();
+        let system_now = clock.system_now();
+        state.entries.sort_by_key(|&(time, _)| time);
+
+        let mut throttle = Throttle::with_clock(state.timeout, state.threshold, clock);
+        for (time, cost) in state.entries {
+            let age = system_now.duration_since(time).unwrap_or_default();
+            if age >= state.timeout || cost == 0 {
+                continue;
+            }
+            let instant = now.checked_sub(age).unwrap_or(now);
+            throttle.deque.push_back((instant, cost));
+            throttle.total += cost;
+        }
+        throttle
+    }
+
+    /// Takes a snapshot of the unexpired operations in the throttle
+    ///
+    /// ```
+    /// # #[cfg(feature = "serde")] {
+    /// use std::time::Duration;
+    /// use throttle::{Throttle, ThrottleState};
+    ///
+    /// let mut throttle = Throttle::new(Duration::from_secs(60), 10);
+    /// throttle.accept_n(3).expect("The throttle is empty");
+    ///
+    /// let json = serde_json::to_string(&throttle.state()).unwrap();
+    /// let state: ThrottleState = serde_json::from_str(&json).unwrap();
+    /// assert_eq!(Throttle::restore(state).size(), 3);
+    /// # }
+    /// ```
+    pub fn state(&mut self) -> ThrottleState {
+        self.flush();
+        let now = self.clock.now();
+        let system_now = self.clock.system_now();
+        ThrottleState {
+            timeout: self.timeout,
+            threshold: self.threshold,
+            entries: self
+                .deque
+                .iter()
+                .map(|&(instant, cost)| (system_now - (now - instant), cost))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{AcceptNError, Clock, SystemClock, Throttle};
+
+/// SyncThrottle is a [`Throttle`] that can be shared between threads.
+///
+/// All methods take `&self`, so a SyncThrottle can be put in an `Arc`, or in a `static` through
+/// [`LazyLock`](std::sync::LazyLock), and used from many threads at once.
+///
+/// SyncThrottle is a convenience wrapper around a `Mutex<Throttle>`, so every call serializes on
+/// one lock and it performs the same as locking a Throttle by hand. Under heavy contention,
+/// [`AtomicGcra`](crate::AtomicGcra) is a lock-free alternative with the same limit, at the cost
+/// of spreading the freed capacity evenly over the timeout.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use throttle::SyncThrottle;
+///
+/// let throttle = Arc::new(SyncThrottle::new(Duration::from_secs(60), 10));
+/// let threads: Vec<_> = (0..4)
+///     .map(|_| {
+///         let throttle = Arc::clone(&throttle);
+///         std::thread::spawn(move || (0..5).filter(|_| throttle.accept().is_ok()).count())
+///     })
+///     .collect();
+/// let accepted: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+/// assert_eq!(accepted, 10);
+/// assert_eq!(throttle.size(), 10);
+/// ```
+pub struct SyncThrottle<C = SystemClock> {
+    inner: Mutex<Throttle<C>>,
+}
+
+impl SyncThrottle {
+    /// Creates a new SyncThrottle
+    pub fn new(timeout: Duration, threshold: usize) -> SyncThrottle {
+        SyncThrottle::with_clock(timeout, threshold, SystemClock)
+    }
+}
+
+impl<C: Clock> SyncThrottle<C> {
+    /// Creates a new SyncThrottle that reads the time from `clock`
+    pub fn with_clock(timeout: Duration, threshold: usize, clock: C) -> SyncThrottle<C> {
+        SyncThrottle {
+            inner: Mutex::new(Throttle::with_clock(timeout, threshold, clock)),
+        }
+    }
+
+    /// Returns the number of remaining items in the throttle
+    pub fn size(&self) -> usize {
+        self.inner.lock().unwrap().size()
+    }
+
+    /// Checks that the throttle is available to accept.
+    ///
+    /// Unlike [`Throttle::available`], the availability may change at any time since other
+    /// threads may accept concurrently.
+    pub fn available(&self) -> bool {
+        self.inner.lock().unwrap().available()
+    }
+
+    /// Attempts to accept an operation and increment the throttle.
+    ///
+    /// See [`Throttle::accept`].
+    pub fn accept(&self) -> Result<(), Instant> {
+        self.inner.lock().unwrap().accept()
+    }
+
+    /// Attempts to accept an operation that costs `cost` units of the threshold.
+    ///
+    /// See [`Throttle::accept_n`].
+    pub fn accept_n(&self, cost: usize) -> Result<(), AcceptNError> {
+        self.inner.lock().unwrap().accept_n(cost)
+    }
+}
@@ -0,0 +1,177 @@
+// throttle
+// Copyright (C) SOFe
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use crate::{nanos_to_duration, AcceptNError, Clock, SystemClock};
+
+/// TokenBucket is a rate limiter with constant memory usage.
+///
+/// The bucket holds up to `capacity` tokens and is refilled continuously at `refill` tokens per
+/// `period`, so partial tokens accumulate between accepts. Each accepted operation takes one
+/// token.
+///
+/// ```
+/// use std::time::Duration;
+/// use throttle::{Clock, MockClock, TokenBucket};
+///
+/// let clock = MockClock::new();
+/// // 2 tokens every 3 seconds, i.e. one token every 1.5 seconds
+/// let mut bucket = TokenBucket::with_clock(2, 2, Duration::from_secs(3), clock.clone());
+///
+/// bucket.accept().expect("The bucket is full");
+/// bucket.accept().expect("The bucket has one more token");
+/// assert_eq!(bucket.size(), 2);
+/// assert_eq!(bucket.accept(), Err(clock.now() + Duration::from_millis(1500)));
+///
+/// clock.advance(Duration::from_secs(1)); // 2/3 of a token is refilled
+/// assert_eq!(bucket.size(), 2);
+/// assert_eq!(bucket.accept(), Err(clock.now() + Duration::from_millis(500)));
+///
+/// clock.advance(Duration::from_millis(500));
+/// assert_eq!(bucket.size(), 1);
+/// bucket.accept().expect("One token has been refilled");
+/// ```
+pub struct TokenBucket<C = SystemClock> {
+    capacity: usize,
+    refill: usize,
+    period: u128,
+    /// Available tokens multiplied by `period` in nanoseconds, so that partial tokens are exact.
+    units: u128,
+    /// Units taken by [`force_n`](TokenBucket::force_n) beyond the available tokens, which are
+    /// repaid before any tokens are refilled
+    debt: u128,
+    updated: Instant,
+    clock: C,
+}
+
+impl TokenBucket {
+    /// Creates a new full TokenBucket
+    ///
+    /// # Panics
+    /// Panics if `refill` or `period` is zero.
+    pub fn new(capacity: usize, refill: usize, period: Duration) -> TokenBucket {
+        TokenBucket::with_clock(capacity, refill, period, SystemClock)
+    }
+}
+
+impl<C: Clock> TokenBucket<C> {
+    /// Creates a new full TokenBucket that reads the time from `clock`
+    ///
+    /// # Panics
+    /// Panics if `refill` or `period` is zero.
+    pub fn with_clock(
+        capacity: usize,
+        refill: usize,
+        period: Duration,
+        clock: C,
+    ) -> TokenBucket<C> {
+        assert!(refill > 0, "refill must be positive");
+        assert!(period > Duration::from_secs(0), "period must be positive");
+        let period = period.as_nanos();
+        TokenBucket {
+            capacity,
+            refill,
+            period,
+            units: capacity as u128 * period,
+            debt: 0,
+            updated: clock.now(),
+            clock,
+        }
+    }
+
+    fn flush(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.updated).as_nanos();
+        let max = self.capacity as u128 * self.period;
+        let mut refilled = elapsed * self.refill as u128;
+        let repaid = refilled.min(self.debt);
+        self.debt -= repaid;
+        refilled -= repaid;
+        self.units = max.min(self.units + refilled);
+        self.updated = now;
+    }
+
+    /// Returns the number of tokens that have been taken and not yet refilled
+    pub fn size(&mut self) -> usize {
+        self.flush();
+        let debt = self.debt.div_ceil(self.period) as usize;
+        self.capacity - (self.units / self.period) as usize + debt
+    }
+
+    /// Checks that the bucket has at least one token.
+    ///
+    /// The same race conditions as [`Throttle::available`](crate::Throttle::available) apply.
+    pub fn available(&mut self) -> bool {
+        self.flush();
+        self.units >= self.period
+    }
+
+    /// Attempts to take a token from the bucket.
+    ///
+    /// On failure, Err is returned with an Instant indicating the time that the bucket has a
+    /// token again.
+    ///
+    /// # Panics
+    /// Panics if the capacity is zero.
+    pub fn accept(&mut self) -> Result<(), Instant> {
+        match self.accept_n(1) {
+            Ok(()) => Ok(()),
+            Err(AcceptNError::Full(at)) => Err(at),
+            Err(AcceptNError::TooLarge) => panic!("TokenBucket capacity is zero"),
+        }
+    }
+
+    /// Attempts to take `cost` tokens from the bucket.
+    ///
+    /// See [`Throttle::accept_n`](crate::Throttle::accept_n) for the meaning of the errors.
+    pub fn accept_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        self.check_n(cost)?;
+        self.units -= cost as u128 * self.period;
+        Ok(())
+    }
+
+    /// Takes `cost` tokens regardless of whether enough are available.
+    ///
+    /// Tokens beyond the available ones are recorded as debt, which blocks further accepts until
+    /// it has been refilled.
+    pub(crate) fn force_n(&mut self, cost: usize) {
+        self.flush();
+        let needed = cost as u128 * self.period;
+        if self.units >= needed {
+            self.units -= needed;
+        } else {
+            self.debt += needed - self.units;
+            self.units = 0;
+        }
+    }
+
+    /// Checks whether `cost` tokens could be taken now, without taking them.
+    ///
+    /// The errors are the same as [`accept_n`](TokenBucket::accept_n).
+    pub fn check_n(&mut self, cost: usize) -> Result<(), AcceptNError> {
+        if cost > self.capacity {
+            return Err(AcceptNError::TooLarge);
+        }
+
+        self.flush();
+        let needed = cost as u128 * self.period + self.debt;
+        if self.units < needed {
+            let wait = (needed - self.units).div_ceil(self.refill as u128);
+            return Err(AcceptNError::Full(self.updated + nanos_to_duration(wait)));
+        }
+        Ok(())
+    }
+}